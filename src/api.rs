@@ -0,0 +1,825 @@
+//! Fetching from the GW2 API: throttling, retries, the response cache and icon downloads.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use futures::{StreamExt, TryStreamExt};
+use serde_json as sj;
+
+use crate::error::{Error, OrShape, Result};
+use crate::output::{Category, Line};
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum ChunkStrategy {
+    /// fill every chunk up to the limit, leaving the remainder last
+    #[default]
+    Sequential,
+    /// spread ids evenly over the fewest chunks that fit the limit
+    Balanced,
+}
+
+/// languages the api localizes names into
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Lang {
+    #[default]
+    En,
+    Es,
+    De,
+    Fr,
+    Zh,
+}
+
+impl Lang {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Es => "es",
+            Lang::De => "de",
+            Lang::Fr => "fr",
+            Lang::Zh => "zh",
+        }
+    }
+}
+
+/// root every endpoint is requested under
+pub const DEFAULT_API_BASE: &str = "https://api.guildwars2.com/v2";
+
+/// per-request timeout of the shared client, in seconds
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// seconds allowed to open a connection, well below the whole request's timeout
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// seconds a response may go without sending anything, well below the whole request's timeout
+pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 15;
+
+/// names the tool to the api, with a link for whoever reads its logs
+pub const USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/Typas/GW2-api-img)"
+);
+
+/// documented limit of the GW2 API, in requests per minute
+pub const DEFAULT_RATE_LIMIT: u32 = 600;
+
+/// token bucket shared by every request of a run
+pub struct RateLimiter {
+    bucket: tokio::sync::Mutex<(f64, Instant)>,
+    capacity: f64,
+    per_second: f64,
+}
+
+impl RateLimiter {
+    /// a limiter letting `rate` requests through per minute, spread evenly
+    pub fn per_minute(rate: u32) -> Self {
+        let per_second = f64::from(rate) / 60.0;
+        // allow a second's worth of requests to burst
+        let capacity = per_second.max(1.0);
+        Self {
+            bucket: tokio::sync::Mutex::new((capacity, Instant::now())),
+            capacity,
+            per_second,
+        }
+    }
+
+    /// wait until the next request may be sent
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let (tokens, last) = &mut *bucket;
+                let now = Instant::now();
+                *tokens =
+                    (*tokens + (now - *last).as_secs_f64() * self.per_second).min(self.capacity);
+                *last = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// directory raw responses are cached in when there is no user cache directory
+pub const CACHE_DIR: &str = ".gw2cache";
+
+/// `gw2-api-img` in the user's cache directory, `$XDG_CACHE_HOME` or `~/.cache`,
+/// else `CACHE_DIR` in the working directory
+pub fn default_cache_dir() -> PathBuf {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    var("XDG_CACHE_HOME")
+        .or_else(|| var("HOME").map(|home| home.join(".cache")))
+        .map_or_else(|| PathBuf::from(CACHE_DIR), |dir| dir.join("gw2-api-img"))
+}
+/// 64-bit FNV-1a of `text`, which unlike `DefaultHasher` stays the same across releases
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// entity files of the cache read or written at once
+const ENTITY_FILES_IN_FLIGHT: usize = 64;
+
+#[derive(Clone, Copy)]
+pub enum CacheMode {
+    /// load from the cache when present, store otherwise
+    Use,
+    /// always fetch, overwriting the cache
+    Refresh,
+    /// neither read nor write the cache
+    Off,
+}
+
+/// raw api responses on disk, one json file per key, and details one file per entity
+///
+/// `build.json` records the game build each key was fetched at, so a
+/// patch makes every older entry stale. Each api base caches into its own
+/// subdirectory, so a mock or a proxy never serves the real api's data.
+pub struct Cache {
+    dir: PathBuf,
+    mode: CacheMode,
+    build: u64,
+    builds: tokio::sync::Mutex<BTreeMap<String, u64>>,
+}
+
+impl Cache {
+    /// a cache in `dir` of the api at `base_url`; nothing is read until `set_build` names the
+    /// current build
+    pub fn new(dir: PathBuf, base_url: &str, mode: CacheMode) -> Self {
+        Self {
+            dir: dir.join(format!(
+                "api-{:016x}",
+                fnv1a(base_url.trim_end_matches('/'))
+            )),
+            mode,
+            build: 0,
+            builds: Default::default(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // nested endpoints such as pvp/amulets stay one file
+        self.dir.join(format!("{}.json", key.replace('/', "_")))
+    }
+
+    /// set the current game build and read which build each key was cached at
+    pub async fn set_build(&mut self, build: u64) -> Result<()> {
+        self.build = build;
+        let path = self.path("build");
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                *self.builds.get_mut() = sj::from_slice(&bytes).map_err(Error::json(format!(
+                    "corrupt cache file {}",
+                    path.display()
+                )))?;
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::io(format!("cannot read {}", path.display()))(e)),
+        }
+    }
+
+    /// the value stored under `key`, if it was stored at the current build
+    pub async fn load(&self, key: &str) -> Result<Option<sj::Value>> {
+        if !matches!(self.mode, CacheMode::Use) {
+            return Ok(None);
+        }
+        if self.builds.lock().await.get(key) != Some(&self.build) {
+            return Ok(None);
+        }
+        let path = self.path(key);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                let value = sj::from_slice(&bytes).map_err(Error::json(format!(
+                    "corrupt cache file {}",
+                    path.display()
+                )))?;
+                Ok(Some(value))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::io(format!("cannot read {}", path.display()))(e)),
+        }
+    }
+
+    /// directory of the entities of `key`, recorded in `build.json` under `key/`
+    fn entity_dir(&self, key: &str) -> (PathBuf, String) {
+        (self.dir.join(key.replace('/', "_")), format!("{key}/"))
+    }
+
+    /// the cached entities of `key` among `ids`, leaving out those not cached yet
+    pub async fn load_entities(&self, key: &str, ids: &[u64]) -> Result<Vec<sj::Value>> {
+        let (dir, build_key) = self.entity_dir(key);
+        if !matches!(self.mode, CacheMode::Use)
+            || self.builds.lock().await.get(&build_key) != Some(&self.build)
+        {
+            return Ok(Vec::new());
+        }
+        let loaded: Vec<Option<sj::Value>> = futures::stream::iter(ids)
+            .map(|id| {
+                let path = dir.join(format!("{id}.json"));
+                async move {
+                    match tokio::fs::read(&path).await {
+                        Ok(bytes) => {
+                            sj::from_slice(&bytes)
+                                .map(Some)
+                                .map_err(Error::json(format!(
+                                    "corrupt cache file {}",
+                                    path.display()
+                                )))
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                        Err(e) => Err(Error::io(format!("cannot read {}", path.display()))(e)),
+                    }
+                }
+            })
+            .buffer_unordered(ENTITY_FILES_IN_FLIGHT)
+            .try_collect()
+            .await?;
+        Ok(loaded.into_iter().flatten().collect())
+    }
+
+    /// add `entities` of `key`, each under its id, dropping those cached at another build
+    pub async fn store_entities(&self, key: &str, entities: &[sj::Value]) -> Result<()> {
+        if matches!(self.mode, CacheMode::Off) || entities.is_empty() {
+            return Ok(());
+        }
+        let (dir, build_key) = self.entity_dir(key);
+        let mut builds = self.builds.lock().await;
+        if builds.get(&build_key) != Some(&self.build) {
+            match tokio::fs::remove_dir_all(&dir).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(Error::io(format!("cannot clear {}", dir.display()))(e));
+                }
+                _ => {}
+            }
+            builds.insert(build_key, self.build);
+        }
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(Error::io(format!("cannot create {}", dir.display())))?;
+        futures::stream::iter(entities)
+            .map(|entity| {
+                let dir = &dir;
+                async move {
+                    let id = entity.get("id").and_then(|id| id.as_u64());
+                    let id = id.or_shape("cannot cache an entity without an id")?;
+                    let path = dir.join(format!("{id}.json"));
+                    let bytes =
+                        sj::to_vec(entity).map_err(Error::json("cannot serialize an entity"))?;
+                    tokio::fs::write(&path, bytes)
+                        .await
+                        .map_err(Error::io(format!("cannot write {}", path.display())))
+                }
+            })
+            .buffer_unordered(ENTITY_FILES_IN_FLIGHT)
+            .try_collect::<()>()
+            .await?;
+
+        let path = self.path("build");
+        let bytes = sj::to_vec(&*builds).map_err(Error::json("cannot serialize builds"))?;
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(Error::io(format!("cannot write {}", path.display())))
+    }
+
+    /// keep `value` under `key`, recording the current build
+    pub async fn store(&self, key: &str, value: &sj::Value) -> Result<()> {
+        if matches!(self.mode, CacheMode::Off) {
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(Error::io(format!("cannot create {}", self.dir.display())))?;
+        let path = self.path(key);
+        let bytes = sj::to_vec(value).map_err(Error::json(format!("cannot serialize {key}")))?;
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(Error::io(format!("cannot write {}", path.display())))?;
+
+        let mut builds = self.builds.lock().await;
+        builds.insert(key.to_owned(), self.build);
+        let path = self.path("build");
+        let bytes = sj::to_vec(&*builds).map_err(Error::json("cannot serialize builds"))?;
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(Error::io(format!("cannot write {}", path.display())))
+    }
+}
+
+/// the one client of a run, keeping connections alive between requests; https negotiates
+/// http/2, which carries every concurrent chunk over a single connection
+///
+/// `timeout` bounds a whole request, `connect_timeout` opening its connection and
+/// `read_timeout` each wait for more of the response.
+pub fn client(
+    timeout: Duration,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+) -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()
+        .map_err(Error::http("cannot build the http client"))
+}
+
+/// the http client together with the limiter guarding it
+pub struct Api {
+    pub client: reqwest::Client,
+    /// endpoints are joined onto this, without a trailing slash
+    pub base_url: String,
+    pub limiter: RateLimiter,
+    pub cache: Cache,
+    pub lang: Lang,
+    /// sent as a bearer token to the api, never to the icon host
+    pub api_key: Option<String>,
+    /// skip chunks that fail instead of aborting, counting them in `failed_chunks`
+    pub best_effort: bool,
+    /// tries per request before a transient failure is reported, at least 1
+    pub max_attempts: u32,
+    pub failed_chunks: AtomicUsize,
+    /// entities received from the api and read from the cache, for --stats
+    pub fetched: AtomicUsize,
+    pub cache_hits: AtomicUsize,
+}
+
+/// how many entities of `json` there are, for the counters of `Api`
+fn entities(json: &sj::Value) -> usize {
+    json.as_array().map_or(0, Vec::len)
+}
+
+/// attempts made for a url failing transiently before giving up, the first one included
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// whether a failed request may succeed when simply sent again
+fn transient(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect()
+}
+
+/// answers worth asking again: throttling, and the api's own failures
+fn retryable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// send what `request` builds under the rate limit, again while it fails transiently; once
+/// `api.max_attempts` are spent, the last 429 or 5xx is returned for the caller to report
+async fn send_retrying(
+    api: &Api,
+    url: &str,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        api.limiter.acquire().await;
+        attempt += 1;
+        let last = attempt >= api.max_attempts;
+        let response = match request().send().await {
+            Ok(response) => response,
+            Err(e) if transient(&e) && !last => {
+                log::warn!("{url}: {e}, retrying");
+                tokio::time::sleep(backoff(attempt)).await;
+                continue;
+            }
+            Err(e) if transient(&e) => {
+                return Err(Error::http(format!("{url} failed {attempt} times"))(e));
+            }
+            Err(e) => return Err(Error::http(format!("request to {url} failed"))(e)),
+        };
+        if !retryable(response.status()) || last {
+            return Ok(response);
+        }
+        log::warn!("{url} returned {}, retrying", response.status());
+        tokio::time::sleep(retry_delay(&response, attempt)).await;
+    }
+}
+
+/// get `url` from the api, with the api key when there is one
+async fn send(api: &Api, url: &str) -> Result<reqwest::Response> {
+    send_retrying(api, url, || {
+        let request = api.client.get(url);
+        match &api.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    })
+    .await
+}
+
+async fn read_json(response: reqwest::Response, url: &str) -> Result<sj::Value> {
+    let status = response.status();
+    let result = response.json::<sj::Value>().await;
+    if !status.is_success() {
+        // prefer the api's own explanation when the body carries one
+        check_api_error(result.unwrap_or_default())?;
+        return Err(Error::Api(format!("{url} returned {status}")));
+    }
+    result.map_err(Error::http(format!("{url} returned invalid json")))
+}
+
+/// the json at `url`, or the api's own error when it refuses
+pub async fn fetch_json(api: &Api, url: &str) -> Result<sj::Value> {
+    read_json(send(api, url).await?, url).await
+}
+
+/// wait asked for by `Retry-After`, else the usual backoff
+fn retry_delay(response: &reqwest::Response, attempt: u32) -> Duration {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| backoff(attempt))
+}
+
+/// exponential backoff from one second, capped at a minute; the jitter of up to half the
+/// delay either way keeps concurrent chunks from retrying in lockstep
+fn backoff(attempt: u32) -> Duration {
+    let delay = Duration::from_secs(1 << (attempt - 1).min(6)).min(Duration::from_secs(60));
+    delay.mul_f64(0.5 + fastrand::f64())
+}
+
+/// turn the api's `{"text": "..."}` error shape into an error carrying its message
+pub fn check_api_error(json: sj::Value) -> Result<sj::Value> {
+    match json.get("text").and_then(|t| t.as_str()) {
+        Some(text) if json.is_object() => Err(Error::Api(format!("GW2 API error: {text}"))),
+        _ => Ok(json),
+    }
+}
+
+/// the current game build, which invalidates the cache when it changes
+pub async fn get_build(api: &Api) -> Result<u64> {
+    fetch_json(api, &format!("{}/build", api.base_url))
+        .await?
+        .get("id")
+        .and_then(|id| id.as_u64())
+        .or_shape("build id")
+}
+
+/// every id of `category`, as the json list the api returns
+pub async fn get_meta(api: &Api, category: &str) -> Result<sj::Value> {
+    let key = format!("{category}_ids");
+    if let Some(cached) = api.cache.load(&key).await? {
+        log::info!("{category}: ids from cache");
+        return Ok(cached);
+    }
+    log::info!("{category}: requesting ids");
+    let url = format!("{}/{}?lang={}", api.base_url, category, api.lang.as_str());
+    let result = check_api_error(fetch_json(api, &url).await?)?;
+    // an empty list is most likely a hiccup of the api, so it is asked again next time
+    if result.as_array().is_some_and(|ids| ids.is_empty()) {
+        log::warn!("{category}: the api listed no ids");
+        return Ok(result);
+    }
+    api.cache.store(&key, &result).await?;
+    Ok(result)
+}
+
+/// cache key of a category's details; names are localized, so each language is kept apart
+fn data_key(api: &Api, category: &str) -> String {
+    format!("{category}_{}", api.lang.as_str())
+}
+
+/// every entity of a category in a single `ids=all` request
+///
+/// Returns `None` when the endpoint refuses `ids=all`, as traits does, so the
+/// caller can fall back to listing ids and fetching them in chunks. Any other
+/// refusal, e.g. of a missing api key, is an error as with every request.
+pub async fn get_all(api: &Api, category: &str) -> Result<Option<sj::Value>> {
+    let key = data_key(api, category);
+    if let Some(cached) = api.cache.load(&key).await? {
+        log::info!("{category}: details from cache");
+        api.cache_hits
+            .fetch_add(entities(&cached), Ordering::Relaxed);
+        return Ok(Some(cached));
+    }
+    log::info!("{category}: requesting ids=all");
+    let url = format!(
+        "{}/{}?ids=all&lang={}",
+        api.base_url,
+        category,
+        api.lang.as_str()
+    );
+    let response = send(api, &url).await?;
+    let status = response.status();
+    // the api refuses ids=all with a 400 or 404 explaining itself in `text`
+    if matches!(
+        status,
+        reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::NOT_FOUND
+    ) {
+        let body = response.json::<sj::Value>().await.unwrap_or_default();
+        if let Some(text) = body.get("text").and_then(|t| t.as_str()) {
+            log::info!("{category}: ids=all refused: {text}");
+            return Ok(None);
+        }
+        return Err(Error::Api(format!("{url} returned {status}")));
+    }
+    let result = check_api_error(read_json(response, &url).await?)?;
+    if !result.is_array() {
+        return Err(Error::shape(format!("{category}: not an array")));
+    }
+    api.fetched.fetch_add(entities(&result), Ordering::Relaxed);
+    api.cache.store(&key, &result).await?;
+    Ok(Some(result))
+}
+
+/// `ids` split into requests of at most `limit` ids each
+pub fn chunk_ids(ids: &[u64], limit: usize, strategy: ChunkStrategy) -> Vec<&[u64]> {
+    match strategy {
+        ChunkStrategy::Sequential => ids.chunks(limit).collect(),
+        ChunkStrategy::Balanced => {
+            let count = ids.len().div_ceil(limit);
+            let mut result = Vec::with_capacity(count);
+            let mut rest = ids;
+            for i in 0..count {
+                // the first (len % count) chunks take one extra id
+                let size = rest.len().div_ceil(count - i);
+                let (head, tail) = rest.split_at(size);
+                result.push(head);
+                rest = tail;
+            }
+            result
+        }
+    }
+}
+
+/// requests kept in flight at once by `get_data` and `download_icons`
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// most ids the api accepts in one request, the limit of traits
+pub const MAX_CHUNK_SIZE: usize = 200;
+
+/// details of `ids`, fetching only those the cache does not hold yet
+///
+/// The api cannot tell which entities a patch changed, so after one every id is
+/// fetched again; within a build, an interrupted or partial run resumes where it stopped.
+pub async fn get_data(
+    api: &Api,
+    ids: &[u64],
+    category: &str,
+    chunk_size: usize,
+    strategy: ChunkStrategy,
+    concurrency: usize,
+) -> Result<sj::Value> {
+    let key = data_key(api, category);
+    let mut result = api.cache.load_entities(&key, ids).await?;
+    let cached: HashSet<u64> = result
+        .iter()
+        .filter_map(|entity| entity.get("id")?.as_u64())
+        .collect();
+    if !cached.is_empty() {
+        log::info!("{category}: {} details from cache", cached.len());
+    }
+    let missing: Vec<u64> = ids
+        .iter()
+        .copied()
+        .filter(|id| !cached.contains(id))
+        .collect();
+    let fetched = fetch_chunks(api, &missing, category, chunk_size, strategy, concurrency).await?;
+    api.cache_hits.fetch_add(cached.len(), Ordering::Relaxed);
+    api.fetched.fetch_add(fetched.len(), Ordering::Relaxed);
+    api.cache.store_entities(&key, &fetched).await?;
+    result.extend(fetched);
+    Ok(sj::Value::from(result))
+}
+
+/// details of `ids`, without the chunks that failed in best-effort mode
+async fn fetch_chunks(
+    api: &Api,
+    ids: &[u64],
+    category: &str,
+    chunk_size: usize,
+    strategy: ChunkStrategy,
+    concurrency: usize,
+) -> Result<Vec<sj::Value>> {
+    // need to split and merge for each chunk_size elements
+    let id_chunks = chunk_ids(ids, chunk_size, strategy);
+    let urls: Vec<String> = id_chunks
+        .into_iter()
+        .map(|x| {
+            x.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .map(|s| {
+            format!(
+                "{}/{}?ids={}&lang={}",
+                api.base_url,
+                category,
+                s,
+                api.lang.as_str()
+            )
+        })
+        .collect();
+
+    // chunks complete out of order, which is fine as every section is sorted later
+    let total = urls.len();
+    let chunks = futures::stream::iter(urls)
+        .map(|url| async move {
+            let chunk = match check_api_error(fetch_json(api, &url).await?)? {
+                sj::Value::Array(items) => items,
+                _ => return Err(Error::shape(format!("{category} chunk: not an array"))),
+            };
+            Ok(chunk)
+        })
+        .buffer_unordered(concurrency)
+        .enumerate()
+        .map(|(i, result)| {
+            if result.is_ok() {
+                log::info!("{category}: chunk {}/{total}", i + 1);
+            }
+            result
+        });
+    let v: Vec<Vec<sj::Value>> = if api.best_effort {
+        let failed = AtomicUsize::new(0);
+        let v: Vec<Vec<sj::Value>> = chunks
+            .filter_map(|result| {
+                let failed = &failed;
+                async move {
+                    result
+                        .inspect_err(|e| {
+                            log::error!("{category}: skipped a chunk: {e:#}");
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        })
+                        .ok()
+                }
+            })
+            .collect()
+            .await;
+        api.failed_chunks
+            .fetch_add(failed.into_inner(), Ordering::Relaxed);
+        v
+    } else {
+        chunks.try_collect().await?
+    };
+    Ok(v.concat())
+}
+
+/// local file name of an icon, e.g. `.../file/<signature>/102834.png` becomes `102834.png`
+fn icon_file_name(url: &str) -> Result<&str> {
+    url.rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| Error::Validation(format!("cannot derive a file name from {url}")))
+}
+
+async fn download_icon(api: &Api, url: &str, path: &Path) -> Result<()> {
+    // icons never change under the same url, so an existing file is kept
+    let exists = tokio::fs::try_exists(path).await;
+    if exists.map_err(Error::io(format!("cannot read {}", path.display())))? {
+        return Ok(());
+    }
+    let response = send_retrying(api, url, || api.client.get(url)).await?;
+    let download = async { response.error_for_status()?.bytes().await };
+    let bytes = download
+        .await
+        .map_err(Error::http(format!("cannot download {url}")))?;
+    // written aside and moved into place whole, so an interrupted run leaves no icon that
+    // looks complete
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    tokio::fs::write(&part, bytes)
+        .await
+        .map_err(Error::io(format!("cannot write {}", part.display())))?;
+    tokio::fs::rename(&part, path)
+        .await
+        .map_err(Error::io(format!(
+            "cannot move {} into place",
+            part.display()
+        )))
+}
+
+/// `path` as a link from a document written into `base`, with `/` between its parts
+fn relative_link(path: &Path, base: &Path) -> Result<String> {
+    // lexically, as neither has to exist yet
+    let resolve = |p: &Path| {
+        let p = if p.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            p
+        };
+        let absolute =
+            std::path::absolute(p).map_err(Error::io(format!("cannot resolve {}", p.display())))?;
+        let mut parts = Vec::new();
+        for part in absolute.components() {
+            match part {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    parts.pop();
+                }
+                part => parts.push(part.as_os_str().to_string_lossy().into_owned()),
+            }
+        }
+        Ok(parts)
+    };
+    let (path, base) = (resolve(path)?, resolve(base)?);
+    let shared = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let ups = std::iter::repeat_n("..".to_owned(), base.len() - shared);
+    Ok(ups
+        .chain(path[shared..].iter().cloned())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// fetch each distinct icon into `dir` once and point the links at the copies, as seen from
+/// documents written into `base`
+pub async fn download_icons(
+    api: &Api,
+    sections: &mut [(Category, Vec<Line>)],
+    dir: &Path,
+    base: &Path,
+    concurrency: usize,
+) -> Result<()> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(Error::io(format!("cannot create {}", dir.display())))?;
+
+    let mut local = HashMap::new();
+    for (_, lines) in sections.iter() {
+        for line in lines {
+            if let Line::Link { icon, .. } = line {
+                if !local.contains_key(icon) {
+                    local.insert(icon.clone(), dir.join(icon_file_name(icon)?));
+                }
+            }
+        }
+    }
+    // distinct urls may still share a file id, so each path is fetched once too
+    let downloads: BTreeMap<&PathBuf, &String> =
+        local.iter().map(|(url, path)| (path, url)).collect();
+    log::info!("icons: {} into {}", downloads.len(), dir.display());
+    futures::stream::iter(downloads)
+        .map(|(path, url)| download_icon(api, url, path))
+        .buffer_unordered(concurrency)
+        .try_collect::<()>()
+        .await?;
+
+    let mut links = HashMap::new();
+    for (url, path) in &local {
+        links.insert(url.as_str(), relative_link(path, base)?);
+    }
+    for (_, lines) in sections.iter_mut() {
+        for line in lines {
+            if let Line::Link { icon, .. } = line {
+                *icon = links[icon.as_str()].clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// icons linked from `sections` that do not answer a HEAD request with 200, each url checked once
+pub async fn broken_icons(
+    api: &Api,
+    sections: &[(Category, Vec<Line>)],
+    concurrency: usize,
+) -> HashSet<String> {
+    let urls: BTreeSet<&String> = sections
+        .iter()
+        .flat_map(|(_, lines)| lines)
+        .filter_map(|line| match line {
+            Line::Link { icon, .. } => Some(icon),
+            Line::Header { .. } => None,
+        })
+        .collect();
+    log::info!("icons: checking {}", urls.len());
+    let broken: HashSet<String> = futures::stream::iter(urls)
+        .map(|url| async move {
+            match send_retrying(api, url, || api.client.head(url)).await {
+                Ok(response) if response.status() == reqwest::StatusCode::OK => None,
+                Ok(response) => {
+                    log::warn!("{url}: {}", response.status());
+                    Some(url.clone())
+                }
+                Err(e) => {
+                    log::warn!("{url}: {e}");
+                    Some(url.clone())
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(std::future::ready)
+        .collect()
+        .await;
+    log::info!("icons: {} broken", broken.len());
+    broken
+}
@@ -0,0 +1,77 @@
+//! The ways fetching, shrinking and rendering can fail, for callers to tell apart.
+
+/// every failure of the library, each keeping the message it is shown with
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// a request could not be sent, or its response not read
+    #[error("{context}")]
+    Http {
+        context: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// the api refused a request, with its status or its own explanation
+    #[error("{0}")]
+    Api(String),
+    /// a cache file or value that is not the json it should be
+    #[error("{context}")]
+    Json {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// json that parsed, but lacks a field or holds one of another type
+    #[error("unexpected json: {field}")]
+    UnexpectedShape { field: String },
+    /// a trait naming a specialization the api did not list
+    #[error("no specialization {id}")]
+    MissingSpecialization { id: i64 },
+    /// input or output failing a check, such as an id list or --validate-grouping
+    #[error("{0}")]
+    Validation(String),
+    /// a local file or directory that cannot be read or written
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// `UnexpectedShape` of `field`, e.g. "buff status" or "skills: not an array"
+    pub fn shape(field: impl Into<String>) -> Self {
+        Error::UnexpectedShape {
+            field: field.into(),
+        }
+    }
+
+    /// wraps an io error with `context`, as in `.map_err(Error::io("cannot read x"))`
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |source| Error::Io { context, source }
+    }
+
+    pub fn json(context: impl Into<String>) -> impl FnOnce(serde_json::Error) -> Self {
+        let context = context.into();
+        move |source| Error::Json { context, source }
+    }
+
+    pub fn http(context: impl Into<String>) -> impl FnOnce(reqwest::Error) -> Self {
+        let context = context.into();
+        move |source| Error::Http { context, source }
+    }
+}
+
+/// `Option` to `UnexpectedShape`, the way anyhow's `context` turns it into an error
+pub(crate) trait OrShape<T> {
+    fn or_shape(self, field: &str) -> Result<T>;
+}
+
+impl<T> OrShape<T> for Option<T> {
+    fn or_shape(self, field: &str) -> Result<T> {
+        self.ok_or_else(|| Error::shape(field))
+    }
+}
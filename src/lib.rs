@@ -0,0 +1,26 @@
+//! Builds reference lists of Guild Wars 2 skill, trait and buff icons from the official API.
+//!
+//! Each step has its module: [`api`] fetches the raw json, [`transform`] shrinks it to the
+//! fields links are built from, and [`output`] groups the entries into [`output::Line`]s and
+//! renders those. Every step fails with an [`error::Error`].
+//!
+//! ```no_run
+//! use gw2img::api::{get_data, get_meta, Api, ChunkStrategy, DEFAULT_CONCURRENCY, MAX_CHUNK_SIZE};
+//! use gw2img::output::{skills_to_lines, to_markdown, GroupBy, LinkStyle};
+//! use gw2img::transform::{shrink_skills, to_ids};
+//!
+//! async fn skills_markdown(api: &Api) -> gw2img::error::Result<Vec<String>> {
+//!     let ids = to_ids(get_meta(api, "skills").await?)?;
+//!     let strategy = ChunkStrategy::Sequential;
+//!     let json = get_data(api, &ids, "skills", MAX_CHUNK_SIZE, strategy, DEFAULT_CONCURRENCY);
+//!     let skills = shrink_skills(json.await?, false, false, false)?;
+//!     let lines = skills_to_lines(&skills, &[], GroupBy::Type, None)?;
+//!     Ok(to_markdown(&lines.iter().collect::<Vec<_>>(), LinkStyle::Reference))
+//! }
+//! ```
+
+pub mod api;
+pub mod error;
+pub mod model;
+pub mod output;
+pub mod transform;
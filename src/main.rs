@@ -1,387 +1,1092 @@
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Context;
+use clap::builder::TypedValueParser;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use futures::stream::{FuturesUnordered, StreamExt};
 use serde_json as sj;
-use tokio_stream::{self as ts, StreamExt};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let specializations_json = get_meta("specializations").await?;
-    let skill_ids_json = get_meta("skills").await?;
-    let trait_ids_json = get_meta("traits").await?;
-    let specialization_ids = to_ids(specializations_json)?;
-    let skill_ids = to_ids(skill_ids_json)?;
-    let trait_ids = to_ids(trait_ids_json)?;
-    let specialization_full = get_data(&specialization_ids, "specializations").await?;
-    let skills_full = get_data(&skill_ids, "skills").await?;
-    let traits_full = get_data(&trait_ids, "traits").await?;
-    let buffs = get_buffs(&traits_full, &skills_full)?;
-    let specializations = shrink_specializations(specialization_full)?;
-    let skills = shrink_skills(skills_full)?;
-    let traits = shrink_traits(traits_full)?;
-    let buff_markdown = buffs_to_markdown(buffs)?;
-    let skill_markdown = skills_to_markdown(skills)?;
-    let trait_markdown = traits_to_markdown(traits, specializations)?;
-
-    buff_markdown
-        .into_iter()
-        .chain(trait_markdown.into_iter())
-        .chain(skill_markdown.into_iter())
-        .for_each(|s| println!("{s}"));
+use gw2img::api::{
+    broken_icons, client, default_cache_dir, download_icons, get_all, get_build, get_data,
+    get_meta, Api, Cache, CacheMode, ChunkStrategy, Lang, RateLimiter, DEFAULT_API_BASE,
+    DEFAULT_CONCURRENCY, DEFAULT_CONNECT_TIMEOUT_SECS, DEFAULT_MAX_ATTEMPTS, DEFAULT_RATE_LIMIT,
+    DEFAULT_READ_TIMEOUT_SECS, DEFAULT_TIMEOUT_SECS, MAX_CHUNK_SIZE,
+};
+use gw2img::error::Error;
+use gw2img::output::{
+    buffs_to_lines, counts, diff_lines, flatten, items_to_lines, links_to_lines, localize,
+    parse_previous, shared_icons, skills_to_lines, split_by_profession, table_of_contents, to_anki,
+    to_bbcode, to_csv, to_html, to_json, to_manifest, to_markdown, to_markdown_part,
+    traits_to_lines, validate_grouping, Category, GroupBy, Line, LinkStyle, Profession, SortBy,
+    UnknownSpecs,
+};
+use gw2img::transform::{
+    get_buffs, normalize_icon_url, parse_id_list, shrink_items, shrink_links, shrink_skills,
+    shrink_specializations, shrink_traits, to_ids,
+};
 
-    Ok(())
+#[tokio::main]
+async fn main() {
+    // progress goes to stderr, keeping stdout for the output itself
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let options = parse_options().and_then(|mut options| {
+        options.select_sections();
+        if options.stdin {
+            options.read_stdin()?;
+        }
+        options.check()?;
+        Ok(options)
+    });
+    let options = match options {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    // with --best-effort the deadline only stops fetching, so what arrived is still written
+    let hard_limit = options.timeout_total.filter(|_| !options.best_effort);
+    let result = match hard_limit {
+        Some(limit) => match tokio::time::timeout(limit, run(&options)).await {
+            Ok(result) => result,
+            Err(_) => {
+                eprintln!("run exceeded --timeout-total of {}s", limit.as_secs());
+                std::process::exit(EXIT_TIMEOUT);
+            }
+        },
+        None => run(&options).await,
+    };
+    if let Err(error) = result {
+        eprintln!("Error: {error:?}");
+        std::process::exit(exit_code(&error));
+    }
 }
 
-async fn get_meta(category: &str) -> anyhow::Result<sj::Value> {
-    let url = format!("https://api.guildwars2.com/v2/{}", category);
-    let result = reqwest::get(url).await?.json::<sj::Value>().await?;
-    Ok(result)
-}
+/// exit code of failures no other code covers, such as failing to write the output
+const EXIT_FAILURE: i32 = 1;
+/// exit code of bad arguments or a bad config file, as clap uses
+const EXIT_USAGE: i32 = 2;
+/// exit code of a network failure or a request the api refused
+const EXIT_NETWORK: i32 = 3;
+/// exit code of a response that is not the json it should be
+const EXIT_DATA: i32 = 4;
+/// exit code when the whole run outlives `--timeout-total`, same as timeout(1)
+const EXIT_TIMEOUT: i32 = 124;
 
-async fn get_data(ids: &[u64], category: &str) -> anyhow::Result<sj::Value> {
-    // need to split and merge for each 200 elements
-    // due to the limit of traits
-    let id_chunks = ids.chunks(200);
-    let urls: Vec<String> = id_chunks
-        .map(|x| {
-            x.iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        })
-        .map(|s| format!("https://api.guildwars2.com/v2/{}?ids={}", category, s))
-        .collect();
+const EXIT_CODES: &str = "Exit codes: 0 success, 1 other failure, 2 bad arguments, \
+    3 network or api failure, 4 unexpected data, 124 --timeout-total exceeded";
 
-    let mut stream = ts::iter(urls);
-    let mut v = Vec::new();
-    while let Some(url) = stream.next().await {
-        let result = reqwest::get(url).await?.json::<sj::Value>().await?;
-        v.push(result);
+/// the class of failure `error` belongs to, by its innermost known cause
+fn exit_code(error: &anyhow::Error) -> i32 {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<Error>() {
+            return match e {
+                Error::Api(_) => EXIT_NETWORK,
+                Error::Http { source, .. } if !source.is_decode() => EXIT_NETWORK,
+                Error::Io { .. } => EXIT_FAILURE,
+                Error::Http { .. }
+                | Error::Json { .. }
+                | Error::UnexpectedShape { .. }
+                | Error::MissingSpecialization { .. }
+                | Error::Validation(_) => EXIT_DATA,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return if e.is_decode() {
+                EXIT_DATA
+            } else {
+                EXIT_NETWORK
+            };
+        }
+        if cause.is::<sj::Error>() {
+            return EXIT_DATA;
+        }
+        if cause.is::<std::io::Error>() {
+            return EXIT_FAILURE;
+        }
     }
-    let result: Vec<sj::Value> = v
-        .into_iter()
-        .map(|jsv| {
-            jsv.as_array()
-                .unwrap()
-                .into_iter()
-                .map(|x| x.clone())
-                .collect::<Vec<sj::Value>>()
-        })
-        .flatten()
-        .collect();
-
-    Ok(sj::Value::from(result))
+    // what is left complains about json of an unexpected shape
+    EXIT_DATA
 }
 
-fn to_ids(json: sj::Value) -> anyhow::Result<Vec<u64>> {
-    json.as_array()
-        .context("not an array")?
-        .into_iter()
-        .map(|x| x.as_u64())
-        .collect::<Option<Vec<u64>>>()
-        .context("fail to convert to ids")
-}
+async fn run(options: &Options) -> anyhow::Result<()> {
+    let begun = Instant::now();
+    let mut api = Api {
+        client: client(
+            Duration::from_secs(options.timeout),
+            Duration::from_secs(options.connect_timeout),
+            Duration::from_secs(options.read_timeout),
+        )?,
+        base_url: options.api_base.trim_end_matches('/').to_owned(),
+        limiter: RateLimiter::per_minute(options.rate_limit),
+        cache: Cache::new(
+            options.cache_dir.clone().unwrap_or_else(default_cache_dir),
+            &options.api_base,
+            options.cache_mode(),
+        ),
+        lang: options.lang,
+        api_key: options.api_key.clone(),
+        best_effort: options.best_effort,
+        max_attempts: options.max_attempts,
+        failed_chunks: AtomicUsize::new(0),
+        fetched: AtomicUsize::new(0),
+        cache_hits: AtomicUsize::new(0),
+    };
+    let caching = !matches!(options.cache_mode(), CacheMode::Off);
+    let mut metadata = None;
+    if caching || options.since.is_some() || options.metadata {
+        let build = get_build(&api).await?;
+        match options.since {
+            Some(since) if since == build => {
+                eprintln!("no new build since {since}");
+                return Ok(());
+            }
+            Some(_) => eprintln!("new build {build}"),
+            None => {}
+        }
+        if caching {
+            api.cache.set_build(build).await?;
+        }
+        if options.metadata {
+            metadata = Some(Metadata {
+                build,
+                lang: options.lang.as_str(),
+                generated: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            });
+        }
+    }
 
-fn get_buffs(traits: &sj::Value, skills: &sj::Value) -> anyhow::Result<BTreeMap<String, String>> {
-    let tmap: Vec<BTreeMap<&str, &sj::Value>> = traits
-        .as_array()
-        .context("input is not array")?
+    let wanted: Vec<Category> = Category::value_variants()
         .iter()
-        .map(|item| {
-            item.as_object()
-                .expect("an object")
-                .iter()
-                .map(|(k, v)| (k.as_str(), v))
-                .collect()
+        .copied()
+        .filter(|&category| options.emits(category))
+        // only rangers have pets
+        .filter(|&category| {
+            category != Category::Pets
+                || options.profession.is_empty()
+                || options.profession.contains(&Profession::Ranger)
         })
         .collect();
-
-    let shrinked_traits: Vec<_> = tmap
+    let mut names: Vec<&str> = wanted.iter().flat_map(|&c| sources(c)).copied().collect();
+    names.sort_unstable();
+    names.dedup();
+    let started = tokio::time::Instant::now();
+    let deadline = options.timeout_total.filter(|_| options.best_effort);
+    let limit = [options.timeout_per_category, deadline]
         .into_iter()
-        .filter(|m| m.get("facts").is_some())
-        .map(|m| m.get("facts").unwrap().as_array().unwrap())
         .flatten()
-        .map(|v| v.as_object().unwrap())
-        .filter(|x| x.get("type").is_some_and(|t| t.as_str().unwrap() == "Buff"))
-        .collect();
-
-    let smap: Vec<BTreeMap<&str, &sj::Value>> = skills
-        .as_array()
-        .context("input is not array")?
-        .iter()
-        .map(|item| {
-            item.as_object()
-                .expect("an object")
-                .iter()
-                .map(|(k, v)| (k.as_str(), v))
-                .collect()
+        .min()
+        .map(|limit| started + limit);
+    // every category is fetched at once, while sections are built in order as theirs arrive
+    let mut pending: FuturesUnordered<_> = names
+        .into_iter()
+        .map(|name| {
+            let api = &api;
+            async move {
+                // the categories fail independently, so each error says which one it was
+                let fetch = async {
+                    let result = get_category(api, name, options).await;
+                    result.with_context(|| format!("cannot fetch {name}"))
+                };
+                let result = match limit {
+                    Some(at) => tokio::time::timeout_at(at, fetch).await,
+                    None => Ok(fetch.await),
+                };
+                (name, result)
+            }
         })
         .collect();
+    let mut late = 0;
+    let mut fetched = HashMap::new();
+    let mut stream = options
+        .streams()
+        .then(|| Stream::new(options, metadata.as_ref()));
+    let mut sections = Vec::new();
+    let mut streamed = Vec::new();
+    for category in wanted {
+        while !sources(category)
+            .iter()
+            .all(|name| fetched.contains_key(name))
+        {
+            let (name, data) = pending.next().await.expect("sources are fetched");
+            let data = match data {
+                Ok(data) => data?,
+                Err(_) if options.best_effort => {
+                    log::error!("{name}: not fetched in time, left out");
+                    late += 1;
+                    sj::json!([])
+                }
+                Err(_) => return Err(Error::Api(format!("{name}: not fetched in time")).into()),
+            };
+            fetched.insert(name, data);
+        }
+        let mut lines = build_section(category, &mut fetched, options)?;
+        if !options.allow_empty && !lines.iter().any(|line| matches!(line, Line::Link { .. })) {
+            log::warn!(
+                "{}: nothing to list, leaving the section out",
+                category.as_str()
+            );
+            continue;
+        }
+        // one spelling per icon keeps the output stable between runs
+        for line in &mut lines {
+            if let Line::Link { icon, .. } = line {
+                *icon = normalize_icon_url(icon);
+            }
+        }
+        localize(&mut lines, options.lang);
+        match &mut stream {
+            Some(stream) => {
+                streamed.push((category, link_count(&lines)));
+                stream.write(&lines)?;
+            }
+            None => sections.push((category, lines)),
+        }
+    }
 
-    let shrinked_skills: Vec<_> = smap
+    if options.verify_icons {
+        let broken = broken_icons(&api, &sections, options.concurrency).await;
+        if options.drop_broken_icons {
+            for (_, lines) in &mut sections {
+                lines.retain(
+                    |line| !matches!(line, Line::Link { icon, .. } if broken.contains(icon)),
+                );
+            }
+        }
+    }
+
+    if options.report_shared_icons {
+        let shared = shared_icons(&sections);
+        for (icon, names) in &shared {
+            eprintln!("{icon}: {}", names.join(", "));
+        }
+        let links: usize = shared.values().map(Vec::len).sum();
+        eprintln!("{} icons are shared by {links} links", shared.len());
+    }
+
+    let emitted: Vec<(Category, usize)> = streamed
         .into_iter()
-        .filter(|m| m.get("facts").is_some())
-        .map(|m| m.get("facts").unwrap().as_array().unwrap())
-        .flatten()
-        .map(|v| v.as_object().unwrap())
-        .filter(|x| x.get("type").is_some_and(|t| t.as_str().unwrap() == "Buff"))
+        .chain(sections.iter().map(|(c, lines)| (*c, link_count(lines))))
         .collect();
+    let print_stats = || {
+        if options.stats {
+            eprintln!("{}", stats(&api, begun.elapsed(), &emitted));
+        }
+    };
 
-    let mut result = BTreeMap::new();
-
-    for buff in shrinked_traits {
-        let s = buff
-            .get("status")
-            .context("cannot find status of a buff")?
-            .as_str()
-            .context("cannot convert buff status to string")?;
-        if result.get(s).is_none() {
-            result.insert(
-                s.to_owned(),
-                buff.get("icon")
-                    .context("cannot find status of a buff")?
-                    .as_str()
-                    .context("cannot convert buff status to string")?
-                    .to_owned(),
-            );
+    if options.count {
+        eprintln!("{}", counts(&sections));
+        print_stats();
+        return Ok(());
+    }
+
+    // before downloading, which rewrites icons to local paths
+    if let Some(path) = &options.manifest {
+        std::fs::write(path, options.json(&to_manifest(&sections)))
+            .with_context(|| format!("cannot write {}", path.display()))?;
+    }
+    // compared before downloading too, as previous outputs and manifests hold the api's urls
+    let mut changes = match &options.diff_against {
+        Some(path) => {
+            let previous = std::fs::read_to_string(path)
+                .with_context(|| format!("cannot read {}", path.display()))?;
+            Some(diff_lines(&parse_previous(&previous), &sections))
+        }
+        None => None,
+    };
+    if let Some(dir) = &options.download_icons {
+        // links are relative to where the document lands, stdout counting as here
+        let base = match (&options.output_dir, &options.output) {
+            (Some(dir), _) => dir.as_path(),
+            (None, Some(file)) => file.parent().unwrap_or(Path::new("")),
+            (None, None) => Path::new(""),
+        };
+        let icons = |sections: &[(Category, Vec<Line>)]| -> Vec<String> {
+            let lines = sections.iter().flat_map(|(_, lines)| lines);
+            lines
+                .filter_map(|line| match line {
+                    Line::Link { icon, .. } => Some(icon.clone()),
+                    Line::Header { .. } => None,
+                })
+                .collect()
+        };
+        let remote = icons(&sections);
+        download_icons(&api, &mut sections, dir, base, options.concurrency).await?;
+        // changed links follow; removed ones keep the url they had, as nothing fetched them
+        if let Some(changes) = &mut changes {
+            let local: HashMap<String, String> = remote.into_iter().zip(icons(&sections)).collect();
+            for line in changes {
+                if let Line::Link { icon, .. } = line {
+                    if let Some(path) = local.get(icon) {
+                        *icon = path.clone();
+                    }
+                }
+            }
         }
     }
 
-    for buff in shrinked_skills {
-        let s = buff
-            .get("status")
-            .context("cannot find status of a buff")?
-            .as_str()
-            .context("cannot convert buff status to string")?;
-        if result.get(s).is_none() {
-            result.insert(
-                s.to_owned(),
-                buff.get("icon")
-                    .context("cannot find status of a buff")?
-                    .as_str()
-                    .context("cannot convert buff status to string")?
-                    .to_owned(),
-            );
+    if stream.is_some() {
+        // already written
+    } else if let Some(mut lines) = changes {
+        localize(&mut lines, options.lang);
+        let lines: Vec<&Line> = lines.iter().collect();
+        let output = to_markdown(&lines, options.link_style).join("\n") + "\n";
+        write_output(options.output.as_deref(), &output)?;
+    } else if let Some(dir) = &options.output_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        for (page, sections) in split_by_profession(sections) {
+            let path = dir.join(format!("{page}.{}", options.format.extension()));
+            write_output(Some(&path), &render(&sections, options, metadata.as_ref())?)?;
         }
+    } else {
+        write_output(
+            options.output.as_deref(),
+            &render(&sections, options, metadata.as_ref())?,
+        )?;
     }
 
-    Ok(result)
+    print_stats();
+    let failed = api.failed_chunks.load(Ordering::Relaxed);
+    let mut missing = Vec::new();
+    if failed > 0 {
+        missing.push(format!("{failed} chunks failed"));
+    }
+    if late > 0 {
+        missing.push(format!("{late} categories were not fetched in time"));
+    }
+    if !missing.is_empty() {
+        let message = format!("{}, the output is incomplete", missing.join(" and "));
+        return Err(Error::Api(message).into());
+    }
+    Ok(())
 }
 
-fn shrink_skills(json: sj::Value) -> anyhow::Result<sj::Value> {
-    let result: Vec<sj::Value> = json
-        .as_array()
-        .context("is not an array")?
-        .into_iter()
-        .map(|v| {
-            v.as_object()
-                .expect("an object")
-                .into_iter()
-                .filter(|(k, _)| match k.as_str() {
-                    "name" | "icon" | "type" | "professions" => true,
-                    _ => false,
-                })
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect::<sj::Map<String, sj::Value>>()
-                .into()
-        })
-        .filter(|v: &sj::Value| {
-            v.as_object()
-                .expect("an object")
-                .iter()
-                .all(|(k, v)| match k.as_str() {
-                    "professions" => v.as_array().map_or(false, |u| u.len() == 1),
-                    _ => true,
-                })
-        })
-        .filter(|v| v.as_object().unwrap().get("type").is_some())
-        .filter(|v| v.as_object().unwrap().get("professions").is_some())
-        .collect();
-    Ok(sj::Value::from(result))
+fn link_count(lines: &[Line]) -> usize {
+    lines
+        .iter()
+        .filter(|line| matches!(line, Line::Link { .. }))
+        .count()
 }
 
-fn shrink_traits(json: sj::Value) -> anyhow::Result<sj::Value> {
-    let result: Vec<sj::Value> = json
-        .as_array()
-        .context("is not an array")?
-        .into_iter()
-        .map(|v| {
-            v.as_object()
-                .unwrap()
-                .into_iter()
-                .filter(|(k, _)| match k.as_str() {
-                    "name" | "icon" | "specialization" => true,
-                    _ => false,
-                })
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect::<sj::Map<String, sj::Value>>()
-                .into()
-        })
+/// one line on what the run fetched, emitted and found in the cache, e.g.
+/// "fetched 2600 entities in 18.4s, emitted 1580 skills / 980 traits / 41 buffs, 62 cache hits"
+fn stats(api: &Api, elapsed: Duration, emitted: &[(Category, usize)]) -> String {
+    let emitted: Vec<String> = emitted
+        .iter()
+        .map(|(category, links)| format!("{links} {}", category.as_str()))
         .collect();
-    Ok(sj::Value::from(result))
+    format!(
+        "fetched {} entities in {:.1}s, emitted {}, {} cache hits",
+        api.fetched.load(Ordering::Relaxed),
+        elapsed.as_secs_f64(),
+        emitted.join(" / "),
+        api.cache_hits.load(Ordering::Relaxed)
+    )
 }
 
-fn shrink_specializations(json: sj::Value) -> anyhow::Result<HashMap<i32, (String, String)>> {
-    let mut result = HashMap::new();
-    for spec in json.as_array().context("is not an array")?.into_iter() {
-        let id = spec
-            .get("id")
-            .context("cannot find id")?
-            .as_u64()
-            .context("cannot cast to u64")? as i32;
-        let specialization = spec
-            .get("name")
-            .context("cannot find spec")?
-            .as_str()
-            .context("cannot cast to str")?
-            .to_owned();
-        let profession = spec
-            .get("profession")
-            .context("cannot find spec")?
-            .as_str()
-            .context("cannot cast to str")?
-            .to_owned();
-        result.insert(id, (profession, specialization));
-    }
-
-    Ok(result)
+/// api categories the section of `category` is built from
+fn sources(category: Category) -> &'static [&'static str] {
+    match category {
+        // buffs are collected from the facts of both traits and skills
+        Category::Buffs => &["skills", "traits"],
+        Category::Traits => &["traits", "specializations"],
+        Category::Skills => &["skills"],
+        Category::Pets => &["pets"],
+        Category::Amulets => &["pvp/amulets"],
+        Category::Currencies => &["currencies"],
+        Category::Minis => &["minis"],
+        Category::Items => &["items"],
+    }
 }
 
-fn buffs_to_markdown(buffs: BTreeMap<String, String>) -> anyhow::Result<Vec<String>> {
-    let mut result = Vec::new();
-    result.push(format!("# Buffs"));
-    for buff in buffs {
-        result.push(format!("[{}]: {}", buff.0, buff.1));
+/// the lines of `category`, taking what only it needs out of `fetched`
+///
+/// Sections are built in `Category` order, so buffs still find the skills and traits.
+fn build_section(
+    category: Category,
+    fetched: &mut HashMap<&str, sj::Value>,
+    options: &Options,
+) -> Result<Vec<Line>, Error> {
+    let mut take = |name| fetched.remove(name).expect("sources are fetched");
+    let flat = |lines: Result<Vec<Line>, Error>| {
+        if options.flat {
+            lines.map(flatten)
+        } else {
+            lines
+        }
+    };
+    match category {
+        Category::Buffs => buffs_to_lines(
+            get_buffs(&fetched["traits"], &fetched["skills"])?,
+            options.lang,
+        ),
+        Category::Traits => flat(traits_to_lines(
+            &shrink_traits(take("traits"), options.with_description)?,
+            shrink_specializations(take("specializations"))?,
+            &options.profession,
+            options.spec_icons,
+            options.group_traits_by_tier,
+            options.unknown_specs,
+            options.sort,
+        )),
+        Category::Skills => flat(skills_to_lines(
+            &shrink_skills(
+                take("skills"),
+                options.multi_profession,
+                options.chains,
+                options.with_description,
+            )?,
+            &options.profession,
+            options.group_by,
+            options.sort,
+        )),
+        Category::Pets => links_to_lines(shrink_links(take("pets"), "pets")?, "Pets"),
+        Category::Amulets => {
+            links_to_lines(shrink_links(take("pvp/amulets"), "amulets")?, "PvP Amulets")
+        }
+        Category::Currencies => links_to_lines(
+            shrink_links(take("currencies"), "currencies")?,
+            "Currencies",
+        ),
+        Category::Minis => links_to_lines(shrink_links(take("minis"), "minis")?, "Minis"),
+        Category::Items => items_to_lines(shrink_items(take("items"), &options.item_type)?),
     }
-    Ok(result)
 }
 
-fn skills_to_markdown(json: sj::Value) -> anyhow::Result<Vec<String>> {
-    let mut skills: Vec<_> = json
-        .as_array()
-        .context("is not an array")?
-        .into_iter()
-        .map(|v| {
-            v.as_object()
-                .unwrap()
-                .into_iter()
-                .map(|(x, y)| match x.as_str() {
-                    "professions" => (x.clone(), y.as_array().unwrap().first().unwrap().clone()),
-                    _ => (x.clone(), y.clone()),
-                })
-                .collect::<HashMap<String, sj::Value>>()
-        })
-        .collect();
-    skills.sort_by_key(|x| {
-        (
-            x.get("professions").unwrap().as_str().unwrap().to_owned(),
-            x.get("type").unwrap().as_str().unwrap().to_owned(),
-            x.get("name").unwrap().as_str().unwrap().to_owned(),
-        )
-    });
+/// output written to stdout a section at a time, as soon as each is built
+struct Stream<'a> {
+    options: &'a Options,
+    /// reference labels of the sections written so far
+    labels: HashSet<String>,
+}
 
-    let (mut last_prof, mut last_type) = ("".to_owned(), "".to_owned());
-    let mut result = Vec::new();
-    result.push(format!("# Skill"));
-    for skill in skills {
-        let prof = skill
-            .get("professions")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_owned();
-        let typ = skill.get("type").unwrap().as_str().unwrap().to_owned();
-        if prof != last_prof {
-            last_prof = prof;
-            last_type = typ;
-            result.push(format!("## {}", &last_prof));
-            result.push(format!("### {}", &last_type));
-        } else if typ != last_type {
-            last_type = typ;
-            result.push(format!("### {}", &last_type));
-        }
-
-        result.push(format!(
-            "[{}]: {}",
-            skill.get("name").unwrap().as_str().unwrap(),
-            skill.get("icon").unwrap().as_str().unwrap()
-        ));
-    }
-    Ok(result)
+impl<'a> Stream<'a> {
+    fn new(options: &'a Options, metadata: Option<&Metadata>) -> Self {
+        if let (Format::Markdown, Some(meta)) = (options.format, metadata) {
+            println!("{}", meta.comment());
+        }
+        Stream {
+            options,
+            labels: HashSet::new(),
+        }
+    }
+
+    fn write(&mut self, lines: &[Line]) -> anyhow::Result<()> {
+        let lines: Vec<&Line> = lines.iter().collect();
+        if self.options.validate_grouping {
+            validate_grouping(&lines)?;
+        }
+        let output = match self.options.format {
+            Format::Bbcode => to_bbcode(&lines),
+            _ => to_markdown_part(&lines, self.options.link_style, &mut self.labels),
+        };
+        let mut stdout = std::io::stdout().lock();
+        for line in output {
+            writeln!(stdout, "{line}")?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
 }
 
-fn traits_to_markdown(
-    mut json: sj::Value,
-    spec_map: HashMap<i32, (String, String)>,
-) -> anyhow::Result<Vec<String>> {
-    for t in json.as_array_mut().context("is not an array")?.iter_mut() {
-        let s = t
-            .get("specialization")
-            .context("no specialization")?
-            .as_u64()
-            .context("cannot cast to u64")? as i32;
-        let prof = spec_map.get(&s).context("cannot find spec")?.0.clone();
-        let spec = spec_map.get(&s).context("cannot find spec")?.1.clone();
-        t.as_object_mut()
-            .context("not an object")?
-            .insert("profession".to_string(), sj::Value::String(prof));
-        t.as_object_mut()
-            .context("not an object")?
-            .insert("spec_str".to_string(), sj::Value::String(spec));
-    }
-    let mut traits: Vec<_> = json
-        .as_array()
-        .context("is not an array")?
-        .into_iter()
-        .map(|v| {
-            v.as_object()
-                .unwrap()
-                .into_iter()
-                .map(|(x, y)| match x.as_str() {
-                    _ => (x.clone(), y.clone()),
+/// config file read from the working directory when --config does not name one
+const DEFAULT_CONFIG: &str = "gw2img.toml";
+
+/// the command line, with defaults for what it leaves out taken from the config file
+///
+/// The file holds the long option names as keys, e.g. `lang = "de"` or `only = ["skills"]`,
+/// and each becomes the default of its option, checked by the same parser as the command line.
+/// The command line and environment variables still win over the file, including over options
+/// that conflict with what they give, and `--no-<flag>` turns off a flag the file turns on.
+fn parse_options() -> anyhow::Result<Options> {
+    let mut command = with_negations(Options::command());
+    let matches = command.clone().get_matches();
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None if Path::new(DEFAULT_CONFIG).exists() => PathBuf::from(DEFAULT_CONFIG),
+        None => return Ok(Options::from_arg_matches(&matches)?),
+    };
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("cannot read {}", path.display()))?;
+    let table: toml::Table =
+        toml::from_str(&text).with_context(|| format!("cannot parse {}", path.display()))?;
+
+    // only boolean flags have negations, the other ids are not known to `matches`
+    let given = |id: &str| {
+        matches.try_contains_id(id).is_ok()
+            && matches.value_source(id).is_some_and(|source| {
+                matches!(source, ValueSource::CommandLine | ValueSource::EnvVariable)
+            })
+    };
+    let options = Options::command();
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        let Some(arg) = options
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str())
+        else {
+            anyhow::bail!("{}: unknown option {key}", path.display());
+        };
+        let overridden = given(&id)
+            || arg.get_long().is_some_and(|long| given(&negation(long)))
+            || options
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .any(|other| given(other.get_id().as_str()));
+        if overridden {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Boolean(b) => vec![b.to_string()],
+            toml::Value::String(s) => vec![s],
+            toml::Value::Integer(n) => vec![n.to_string()],
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    toml::Value::String(s) => Ok(s.clone()),
+                    toml::Value::Integer(n) => Ok(n.to_string()),
+                    _ => anyhow::bail!("{}: {key} holds an unsupported value", path.display()),
                 })
-                .collect::<HashMap<String, sj::Value>>()
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            _ => anyhow::bail!("{}: {key} holds an unsupported value", path.display()),
+        };
+        command = command.mut_arg(id, |arg| arg.default_values(values));
+    }
+    // only a value from the file can fail now, the command line having parsed above
+    let matches = command
+        .try_get_matches()
+        .with_context(|| format!("in {}", path.display()))?;
+    Ok(Options::from_arg_matches(&matches)?)
+}
+
+/// the flag that turns off boolean flag `long`: `no-<long>`, or `<flag>` for a `no-<flag>`
+fn negation(long: &str) -> String {
+    match long.strip_prefix("no-") {
+        Some(flag) => flag.to_owned(),
+        None => format!("no-{long}"),
+    }
+}
+
+/// `command` with a hidden negation of each boolean flag, the last of the two given winning
+fn with_negations(command: clap::Command) -> clap::Command {
+    let flags: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+        .filter_map(|arg| Some((arg.get_id().clone(), negation(arg.get_long()?))))
+        .filter(|(_, negation)| {
+            !command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(negation.as_str()))
         })
         .collect();
-    traits.sort_by_key(|x| {
-        (
-            x.get("profession").unwrap().as_str().unwrap().to_owned(),
-            x.get("spec_str").unwrap().as_str().unwrap().to_owned(),
-            x.get("name").unwrap().as_str().unwrap().to_owned(),
+    flags.into_iter().fold(command, |command, (id, negation)| {
+        let arg = Arg::new(negation.clone())
+            .long(negation.clone())
+            .action(ArgAction::SetTrue)
+            .hide(true)
+            .overrides_with(id.clone());
+        command
+            .arg(arg)
+            .mut_arg(id, |arg| arg.overrides_with(negation))
+    })
+}
+
+/// where the output came from, recorded at its top with --metadata
+struct Metadata {
+    build: u64,
+    lang: &'static str,
+    generated: String,
+}
+
+impl Metadata {
+    /// the html comment markdown and html output start with
+    fn comment(&self) -> String {
+        format!(
+            "<!-- generated from build {}, lang={}, {} -->",
+            self.build, self.lang, self.generated
         )
-    });
+    }
+}
+
+/// `sections` in the chosen format, one line per line
+fn render(
+    sections: &[(Category, Vec<Line>)],
+    options: &Options,
+    metadata: Option<&Metadata>,
+) -> anyhow::Result<String> {
+    let lines: Vec<&Line> = sections.iter().flat_map(|(_, lines)| lines).collect();
+    if options.validate_grouping {
+        validate_grouping(&lines)?;
+    }
+    let mut output = match options.format {
+        Format::Markdown if options.toc => {
+            let mut output = table_of_contents(&lines);
+            output.push(String::new());
+            output.extend(to_markdown(&lines, options.link_style));
+            output
+        }
+        Format::Markdown => to_markdown(&lines, options.link_style),
+        Format::Bbcode => to_bbcode(&lines),
+        Format::Anki => to_anki(&lines),
+        Format::Html => to_html(&lines),
+        Format::Json => {
+            let mut json = to_json(sections, options.group_by);
+            if let (Some(meta), Some(object)) = (metadata, json.as_object_mut()) {
+                let meta = sj::json!({
+                    "build": meta.build,
+                    "lang": meta.lang,
+                    "generated": meta.generated,
+                });
+                object.insert("_meta".to_owned(), meta);
+            }
+            vec![options.json(&json)]
+        }
+        Format::Csv => to_csv(sections, options.group_by),
+    };
+    // bbcode and csv have no comments to carry it
+    if let Some(meta) = metadata {
+        let comment = meta.comment();
+        match options.format {
+            Format::Markdown => output.insert(0, comment),
+            Format::Html => output.insert(1, comment),
+            Format::Bbcode | Format::Anki | Format::Json | Format::Csv => {}
+        }
+    }
+    let mut content = String::new();
+    for line in output {
+        content.push_str(&line);
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+/// write `content` to `path`, or stdout without one
+fn write_output(path: Option<&Path>, content: &str) -> anyhow::Result<()> {
+    match path {
+        // leave an identical file alone, so its mtime and version control stay quiet
+        Some(path) if std::fs::read(path).is_ok_and(|old| old == content.as_bytes()) => {
+            log::info!("{}: no changes", path.display());
+        }
+        Some(path) => std::fs::write(path, content)
+            .with_context(|| format!("cannot write {}", path.display()))?,
+        None => std::io::stdout().lock().write_all(content.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum Format {
+    #[default]
+    Markdown,
+    /// forum markup, as used on the official GW2 forums
+    Bbcode,
+    /// a tab separated flashcard deck for anki, best with --download-icons
+    Anki,
+    /// a standalone page showing the icons of each group as a grid
+    Html,
+    /// one object keyed by section, for other tools to consume
+    Json,
+    /// one table per section, separated by a blank line; combine with --only for a single table
+    Csv,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Bbcode | Format::Anki => "txt",
+            Format::Html => "html",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+/// Generate reference-style markdown links to GW2 skill, trait and buff icons
+#[derive(Parser)]
+#[command(version, after_help = EXIT_CODES)]
+struct Options {
+    /// Read default options from this toml file instead of ./gw2img.toml; --no-<flag> turns off
+    /// a flag it turns on
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Only emit these sections
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "skip")]
+    only: Vec<Category>,
+
+    /// Emit buffs, traits and skills except these; the other sections are only emitted by --only
+    #[arg(long, value_enum, value_delimiter = ',')]
+    skip: Vec<Category>,
+
+    /// Emit the skills section, as --only skills; combines with --traits and --buffs
+    #[arg(long, conflicts_with = "skip")]
+    skills: bool,
+
+    /// Emit the traits section, as --only traits
+    #[arg(long, conflicts_with = "skip")]
+    traits: bool,
+
+    /// Emit the buffs section, as --only buffs
+    #[arg(long, conflicts_with = "skip")]
+    buffs: bool,
+
+    /// Only emit skills and traits of these professions
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true)]
+    profession: Vec<Profession>,
+
+    /// What skills are grouped by under each profession
+    #[arg(long, value_enum, default_value_t)]
+    group_by: GroupBy,
+
+    /// List skills and traits in one alphabetical list, without profession or type headers
+    #[arg(long)]
+    flat: bool,
+
+    /// Fetch only these ids of the single category given with --only, e.g. --only skills
+    #[arg(long, alias = "item-ids", value_delimiter = ',', value_name = "IDS")]
+    ids: Vec<u64>,
+
+    /// Order within each group; skills are sorted by name and traits in game order otherwise
+    #[arg(long, value_enum)]
+    sort: Option<SortBy>,
+
+    /// Read ids as with --ids from stdin, comma separated or one per line, optionally prefixed
+    /// with their category as in skills:1,2,3
+    #[arg(long)]
+    stdin: bool,
+
+    /// Only emit items of these types, e.g. Weapon,Armor
+    #[arg(long, value_delimiter = ',', value_name = "TYPES")]
+    item_type: Vec<String>,
+
+    /// Link each specialization's icon right under its header
+    #[arg(long)]
+    spec_icons: bool,
+
+    /// Group each specialization's traits under Minor, Adept, Master and Grandmaster headers
+    #[arg(long)]
+    group_traits_by_tier: bool,
+
+    /// Give skill and trait links their description as a title, shown on hover in markdown
+    #[arg(long)]
+    with_description: bool,
+
+    /// Check every icon with a HEAD request, warning about those not found
+    #[arg(long)]
+    verify_icons: bool,
+
+    /// Leave out entries whose icon failed --verify-icons
+    #[arg(long, requires = "verify_icons")]
+    drop_broken_icons: bool,
+
+    /// List icons linked under more than one name, to stderr
+    #[arg(long)]
+    report_shared_icons: bool,
+
+    /// Emit the header of a section even when it has no entries
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Only print how many entries each section would hold, after filtering, to stderr
+    #[arg(long, alias = "dry-run")]
+    count: bool,
+
+    /// Print what was fetched, emitted and read from the cache, and how long it took, to stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// What to do with traits of a specialization the api does not list
+    #[arg(long, value_enum, default_value_t)]
+    unknown_specs: UnknownSpecs,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Indent json output and the manifest for reading
+    #[arg(long)]
+    pretty: bool,
+
+    /// How markdown links to each icon
+    #[arg(long, value_enum, default_value_t)]
+    link_style: LinkStyle,
+
+    /// Start markdown output with a table of contents linking to every header
+    #[arg(long)]
+    toc: bool,
+
+    /// Write to this file instead of stdout
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Also write a json object mapping every name to its icon url, per section
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+
+    /// Write only the links added, changed or removed since FILE, a previous markdown output
+    /// or --manifest, as markdown
+    #[arg(long, value_name = "FILE", conflicts_with = "output_dir")]
+    diff_against: Option<PathBuf>,
+
+    /// Write one file per profession into this directory, plus one per other section
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+
+    /// Save every icon into this directory and link to the local copies, relative to the file
+    /// written
+    #[arg(long, value_name = "DIR")]
+    download_icons: Option<PathBuf>,
+
+    /// Language of the names, and of the headers and groups they are listed under
+    #[arg(long, value_enum, default_value_t)]
+    lang: Lang,
+
+    /// Most ids fetched per request
+    #[arg(long, default_value_t = MAX_CHUNK_SIZE, value_parser = parse_chunk_size)]
+    chunk_size: usize,
 
-    let (mut last_prof, mut last_spec) = ("".to_owned(), "".to_owned());
-    let mut result = Vec::new();
-    result.push(format!("# Traits"));
-    for t in traits {
-        let prof = t
-            .get("profession")
-            .context("cannot get prof")?
-            .as_str()
-            .context("cannot cast to str")?
-            .to_owned();
-        let spec = t
-            .get("spec_str")
-            .context("cannot get spec")?
-            .as_str()
-            .context("cannot cast to str")?
-            .to_owned();
-        if prof != last_prof {
-            last_prof = prof;
-            last_spec = spec;
-            result.push(format!("## {}", &last_prof));
-            result.push(format!("### {}", &last_spec));
-        } else if spec != last_spec {
-            last_spec = spec;
-            result.push(format!("### {}", &last_spec));
-        }
-
-        result.push(format!(
-            "[{}]: {}",
-            t.get("name").unwrap().as_str().unwrap(),
-            t.get("icon").unwrap().as_str().unwrap()
-        ));
-    }
-    Ok(result)
+    /// How ids are split into requests of at most --chunk-size
+    #[arg(long, value_enum, default_value_t)]
+    chunk_strategy: ChunkStrategy,
+
+    /// Requests kept in flight at once, at least 1
+    ///
+    /// Raising it never exceeds --rate-limit, which still caps how many requests start per minute.
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY,
+          value_parser = clap::value_parser!(u16).map(|n| usize::from(n).max(1)))]
+    concurrency: usize,
+
+    /// Requests per minute
+    #[arg(long, default_value_t = DEFAULT_RATE_LIMIT,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit: u32,
+
+    /// Tries per request, the first included, while the api answers 429 or 5xx or the request
+    /// times out; waits grow from 1s, or follow the api's Retry-After
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_ATTEMPTS,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: u32,
+
+    /// Fetch each category with one ids=all request where the endpoint allows it
+    #[arg(long)]
+    ids_all: bool,
+
+    /// Fetch only the first N ids of each category, for a quick sample; --ids is cut to N too
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Key for authenticated endpoints, sent as a bearer token
+    #[arg(long, env = "GW2_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Root of the api, e.g. a mock server or a proxy
+    #[arg(long, value_name = "URL", env = "GW2_API_BASE", default_value = DEFAULT_API_BASE)]
+    api_base: String,
+
+    /// Give up on a single request after this many seconds; it is retried like a 429
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_TIMEOUT_SECS)]
+    timeout: u64,
+
+    /// Give up connecting to a host after this many seconds, counted within --timeout
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_CONNECT_TIMEOUT_SECS)]
+    connect_timeout: u64,
+
+    /// Give up on a response that sends nothing for this many seconds, counted within --timeout
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_READ_TIMEOUT_SECS)]
+    read_timeout: u64,
+
+    /// Abort the whole run after this many seconds; with --best-effort, stop fetching instead
+    /// and write what arrived
+    #[arg(long, alias = "deadline", value_name = "SECS", value_parser = parse_secs)]
+    timeout_total: Option<Duration>,
+
+    /// Give up on a category not fetched within this many seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    timeout_per_category: Option<Duration>,
+
+    /// Keep going when a chunk fails, writing what was fetched and exiting nonzero at the end
+    #[arg(long)]
+    best_effort: bool,
+
+    /// Record the game build, language and time of the run at the top of markdown, html and json
+    #[arg(long)]
+    metadata: bool,
+
+    /// Exit without fetching anything when the game build is still this one
+    #[arg(long, value_name = "BUILD")]
+    since: Option<u64>,
+
+    /// Ignore the response cache entirely
+    #[arg(long, conflicts_with = "refresh")]
+    no_cache: bool,
+
+    /// Where responses are cached, one subdirectory per --api-base
+    /// [default: $XDG_CACHE_HOME/gw2-api-img or ~/.cache/gw2-api-img]
+    #[arg(long, value_name = "DIR", env = "GW2IMG_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Fetch everything again, overwriting the response cache
+    #[arg(long)]
+    refresh: bool,
+
+    /// Keep skills shared by several professions, listed under each of them
+    #[arg(long)]
+    multi_profession: bool,
+
+    /// Follow skill chains and flip-overs, listing each step after the skill it starts from
+    #[arg(long)]
+    chains: bool,
+
+    /// Check that every entry is listed under its own headers
+    #[arg(long)]
+    validate_grouping: bool,
+}
+
+impl Options {
+    /// whether sections are written as they complete, which only line-based formats going
+    /// to stdout allow; a file is still written at once so an unchanged one is left alone
+    fn streams(&self) -> bool {
+        matches!(self.format, Format::Markdown | Format::Bbcode)
+            && !self.toc
+            && !self.count
+            && !self.verify_icons
+            && !self.report_shared_icons
+            && self.output.is_none()
+            && self.output_dir.is_none()
+            && self.manifest.is_none()
+            && self.download_icons.is_none()
+            && self.diff_against.is_none()
+    }
+
+    /// add the sections named by --skills, --traits and --buffs to --only
+    fn select_sections(&mut self) {
+        let flags = [
+            (self.skills, Category::Skills),
+            (self.traits, Category::Traits),
+            (self.buffs, Category::Buffs),
+        ];
+        for (set, category) in flags {
+            if set && !self.only.contains(&category) {
+                self.only.push(category);
+            }
+        }
+    }
+
+    /// add the ids listed on stdin to --ids, and their category to --only when they name one
+    fn read_stdin(&mut self) -> anyhow::Result<()> {
+        let input = std::io::read_to_string(std::io::stdin()).context("cannot read stdin")?;
+        let (category, ids) = parse_id_list(&input).context("on stdin")?;
+        if let Some(category) = category {
+            anyhow::ensure!(
+                self.only.is_empty() || self.only == [category],
+                "stdin lists {} but --only asks for others",
+                category.as_str()
+            );
+            self.only = vec![category];
+        }
+        self.ids.extend(ids);
+        Ok(())
+    }
+
+    /// what clap cannot check on its own
+    fn check(&self) -> anyhow::Result<()> {
+        // buffs are gathered from both skills and traits, whose ids differ
+        anyhow::ensure!(
+            self.ids.is_empty() || matches!(self.only[..], [only] if only != Category::Buffs),
+            "--ids needs --only with a single category other than buffs"
+        );
+        Ok(())
+    }
+
+    fn emits(&self, category: Category) -> bool {
+        if self.only.is_empty() {
+            let opt_in = matches!(
+                category,
+                Category::Pets
+                    | Category::Amulets
+                    | Category::Currencies
+                    | Category::Items
+                    | Category::Minis
+            );
+            !opt_in && !self.skip.contains(&category)
+        } else {
+            self.only.contains(&category)
+        }
+    }
+
+    fn json(&self, value: &sj::Value) -> String {
+        if self.pretty {
+            // the alternate form is serde_json's pretty printer
+            format!("{value:#}")
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn cache_mode(&self) -> CacheMode {
+        if self.no_cache {
+            CacheMode::Off
+        } else if self.refresh {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Use
+        }
+    }
+}
+
+fn parse_chunk_size(s: &str) -> anyhow::Result<usize> {
+    let size = s.parse()?;
+    anyhow::ensure!(
+        (1..=MAX_CHUNK_SIZE).contains(&size),
+        "must be between 1 and {MAX_CHUNK_SIZE}"
+    );
+    Ok(size)
+}
+
+fn parse_secs(s: &str) -> Result<Duration, std::num::ParseIntError> {
+    s.parse().map(Duration::from_secs)
+}
+
+/// list every id of a category, then fetch their details
+async fn get_category(api: &Api, category: &str, options: &Options) -> Result<sj::Value, Error> {
+    // specializations only name the traits' headers, so all of them are needed
+    let limit = match category {
+        "specializations" => usize::MAX,
+        _ => options.limit.unwrap_or(usize::MAX),
+    };
+    if category != "specializations" && !options.ids.is_empty() {
+        let ids = &options.ids[..limit.min(options.ids.len())];
+        return get_data(
+            api,
+            ids,
+            category,
+            options.chunk_size,
+            options.chunk_strategy,
+            options.concurrency,
+        )
+        .await;
+    }
+    // ids=all would fetch everything the limit is there to spare
+    if options.ids_all && limit == usize::MAX {
+        if let Some(all) = get_all(api, category).await? {
+            return Ok(all);
+        }
+    }
+    let mut ids = to_ids(get_meta(api, category).await?)?;
+    ids.truncate(limit);
+    get_data(
+        api,
+        &ids,
+        category,
+        options.chunk_size,
+        options.chunk_strategy,
+        options.concurrency,
+    )
+    .await
 }
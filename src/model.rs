@@ -0,0 +1,70 @@
+//! Typed shapes of the API's json, so a changed shape fails when it is read rather than
+//! midway through rendering.
+//!
+//! Only the fields this crate uses are declared; the rest of each object is ignored.
+
+use serde::Deserialize;
+
+/// one fact of a skill or trait, such as the buff it applies
+#[derive(Debug, Deserialize)]
+pub struct Fact {
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// the buff's name, on facts of kind `Buff`
+    pub status: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// the facts of a skill or trait, including those a trait changes
+#[derive(Debug, Deserialize)]
+pub struct Facts {
+    #[serde(default)]
+    pub facts: Vec<Fact>,
+    #[serde(default)]
+    pub traited_facts: Vec<Fact>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Skill {
+    /// what chains and flip-overs refer to
+    pub id: u64,
+    pub name: String,
+    pub icon: Option<String>,
+    /// Weapon, Heal, Utility, Elite, Profession, ...
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Weapon_1, Heal, Utility, Elite, ...
+    pub slot: Option<String>,
+    #[serde(default)]
+    pub professions: Vec<String>,
+    pub prev_chain: Option<u64>,
+    pub next_chain: Option<u64>,
+    pub flip_skill: Option<u64>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trait {
+    pub name: String,
+    pub icon: Option<String>,
+    pub specialization: i32,
+    /// 1 to 3, adept to grandmaster
+    pub tier: Option<u64>,
+    /// Minor or Major
+    pub slot: Option<String>,
+    /// place within its tier
+    pub order: Option<u64>,
+    pub description: Option<String>,
+}
+
+/// what a trait needs to know about its specialization
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Specialization {
+    pub id: i32,
+    pub profession: String,
+    pub name: String,
+    /// core specializations may omit the flag
+    #[serde(default)]
+    pub elite: bool,
+    pub icon: Option<String>,
+}
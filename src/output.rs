@@ -0,0 +1,2275 @@
+//! Grouping entries into headed sections and rendering them to each output format.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use clap::ValueEnum;
+use serde_json as sj;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::api::Lang;
+use crate::error::{Error, OrShape, Result};
+use crate::model::{Skill, Specialization, Trait};
+
+/// sections of the output, in the order they are emitted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Category {
+    Buffs,
+    Traits,
+    Skills,
+    /// ranger pets, not emitted unless asked for with --only
+    Pets,
+    /// pvp amulets, not emitted unless asked for with --only
+    Amulets,
+    /// wallet currencies, e.g. karma or spirit shards, not emitted unless asked for with --only
+    Currencies,
+    /// miniatures, not emitted unless asked for with --only
+    Minis,
+    /// not emitted unless asked for with --only, as there are tens of thousands
+    Items,
+}
+
+impl Category {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::Buffs => "buffs",
+            Category::Traits => "traits",
+            Category::Skills => "skills",
+            Category::Pets => "pets",
+            Category::Amulets => "amulets",
+            Category::Currencies => "currencies",
+            Category::Minis => "minis",
+            Category::Items => "items",
+        }
+    }
+
+    /// field names of the groups a link of this section carries
+    pub fn group_fields(self, group_by: GroupBy) -> &'static [&'static str] {
+        match (self, group_by) {
+            (Category::Pets | Category::Amulets | Category::Currencies | Category::Minis, _) => &[],
+            (Category::Buffs, _) => &["kind"],
+            (Category::Traits, _) => &["profession", "specialization"],
+            (Category::Items, _) => &["rarity"],
+            (Category::Skills, GroupBy::Type) => &["profession", "type"],
+            (Category::Skills, GroupBy::Slot) => &["profession", "slot"],
+        }
+    }
+}
+
+/// what skills are grouped by under each profession
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum GroupBy {
+    /// Weapon, Heal, Utility, Elite, Profession, ...
+    #[default]
+    Type,
+    /// Weapon_1, Heal, Utility, Elite, ...; skills without one use their type
+    Slot,
+}
+
+/// order of the entries within each group
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortBy {
+    /// alphabetical, ignoring case and accents
+    Name,
+    /// as laid out in game: skills by slot, traits by tier and place in the tree
+    Ingame,
+}
+
+/// one line of output, before it is rendered to a concrete format
+#[derive(Clone)]
+pub enum Line {
+    Header {
+        level: usize,
+        title: String,
+    },
+    Link {
+        name: String,
+        icon: String,
+        /// the sub-headers this entry belongs under, outermost first
+        groups: Vec<String>,
+        /// shown on hover where the format allows, such as a skill's description
+        title: Option<String>,
+    },
+}
+
+impl Line {
+    fn header(level: usize, title: &str) -> Self {
+        Line::Header {
+            level,
+            title: title.to_owned(),
+        }
+    }
+}
+
+/// how markdown links to an icon
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum LinkStyle {
+    /// `[name]: url` definitions, for referencing from other pages
+    #[default]
+    Reference,
+    /// `![name](url)` images, rendered in place
+    Inline,
+}
+
+/// markdown headers and links, one per line
+pub fn to_markdown(lines: &[&Line], style: LinkStyle) -> Vec<String> {
+    to_markdown_part(lines, style, &mut HashSet::new())
+}
+
+/// `to_markdown` of one part of a document, `used` holding the labels of the parts before it
+pub fn to_markdown_part(
+    lines: &[&Line],
+    style: LinkStyle,
+    used: &mut HashSet<String>,
+) -> Vec<String> {
+    // reference ids are matched case-insensitively across the whole document,
+    // so repeated names get " (2)", " (3)", ... appended
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Header { level, title } => format!("{} {}", "#".repeat(*level), title),
+            Line::Link {
+                name, icon, title, ..
+            } => match style {
+                LinkStyle::Reference => {
+                    let mut label = name.clone();
+                    let mut n = 1;
+                    while !used.insert(label.to_lowercase()) {
+                        n += 1;
+                        label = format!("{name} ({n})");
+                    }
+                    let title = title.as_deref().map(escape_title).unwrap_or_default();
+                    format!("[{}]: {}{title}", escape_label(&label), icon)
+                }
+                LinkStyle::Inline => {
+                    let title = title.as_deref().map(escape_title).unwrap_or_default();
+                    format!("![{}]({}{title})", escape_label(name), icon)
+                }
+            },
+        })
+        .collect()
+}
+
+/// a nested list linking to every header, using the anchors GitHub gives them
+pub fn table_of_contents(lines: &[&Line]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut result = Vec::new();
+    for line in lines {
+        if let Line::Header { level, title } = line {
+            // repeated headers get -1, -2, ... appended in document order
+            let base = slug(title);
+            let count = seen.entry(base.clone()).or_default();
+            let anchor = match *count {
+                0 => base,
+                n => format!("{base}-{n}"),
+            };
+            *count += 1;
+            let indent = "  ".repeat(level - 1);
+            result.push(format!("{indent}- [{}](#{anchor})", escape_label(title)));
+        }
+    }
+    result
+}
+
+/// github's heading anchor: lowercased, punctuation dropped, spaces as hyphens
+fn slug(title: &str) -> String {
+    title
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// backslash-escape what would end or nest a reference link label
+fn escape_label(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '\\' | '[' | ']') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// ` "title"` to follow a link's url, with quotes and backslashes escaped
+fn escape_title(title: &str) -> String {
+    let escaped = title.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(" \"{escaped}\"")
+}
+
+/// forum bbcode, with headers in bold and each icon as an image
+pub fn to_bbcode(lines: &[&Line]) -> Vec<String> {
+    // bbcode has no escape syntax, so brackets in names become entities
+    let escape = |s: &str| s.replace('[', "&#91;").replace(']', "&#93;");
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Header { level: 1, title } => {
+                format!("[size=150][b]{}[/b][/size]", escape(title))
+            }
+            Line::Header { level: 2, title } => format!("[b]{}[/b]", escape(title)),
+            Line::Header { title, .. } => format!("[i]{}[/i]", escape(title)),
+            Line::Link { name, icon, .. } => format!("[img]{}[/img] {}", icon, escape(name)),
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// a tab separated anki deck, one card per link with the icon on the front
+///
+/// Downloaded icons are referred to by file name, as anki looks them up in its media folder.
+pub fn to_anki(lines: &[&Line]) -> Vec<String> {
+    // a tab or line break would end the field or the card
+    let field = |s: &str| escape_html(s).replace(['\t', '\n'], " ");
+    let mut result = vec!["#separator:tab".to_owned(), "#html:true".to_owned()];
+    for line in lines {
+        if let Line::Link {
+            name, icon, groups, ..
+        } = line
+        {
+            let src = if icon.contains("://") {
+                icon.as_str()
+            } else {
+                icon.rsplit(['/', '\\']).next().unwrap_or_default()
+            };
+            let mut back = field(name);
+            if !groups.is_empty() {
+                back.push_str("<br>");
+                back.push_str(&field(&groups.join(" / ")));
+            }
+            result.push(format!("<img src=\"{}\">\t{back}", field(src)));
+        }
+    }
+    result
+}
+
+/// a standalone page with the icons of each group laid out in a grid
+pub fn to_html(lines: &[&Line]) -> Vec<String> {
+    let mut result: Vec<String> = [
+        "<!DOCTYPE html>",
+        "<html>",
+        "<head>",
+        "<meta charset=\"utf-8\">",
+        "<title>GW2 icons</title>",
+        "<style>",
+        ".grid { display: flex; flex-wrap: wrap; gap: 4px; }",
+        ".grid img { width: 64px; height: 64px; }",
+        "</style>",
+        "</head>",
+        "<body>",
+    ]
+    .map(str::to_owned)
+    .into();
+    let mut in_grid = false;
+    for line in lines {
+        match line {
+            Line::Header { level, title } => {
+                if in_grid {
+                    result.push("</div>".to_owned());
+                    in_grid = false;
+                }
+                result.push(format!("<h{level}>{}</h{level}>", escape_html(title)));
+            }
+            Line::Link { name, icon, .. } => {
+                if !in_grid {
+                    result.push("<div class=\"grid\">".to_owned());
+                    in_grid = true;
+                }
+                let name = escape_html(name);
+                result.push(format!(
+                    "<img src=\"{}\" alt=\"{name}\" title=\"{name}\">",
+                    escape_html(icon)
+                ));
+            }
+        }
+    }
+    if in_grid {
+        result.push("</div>".to_owned());
+    }
+    result.extend(["</body>".to_owned(), "</html>".to_owned()]);
+    result
+}
+
+/// every link per section as an object of its name, icon and groups
+pub fn to_json(sections: &[(Category, Vec<Line>)], group_by: GroupBy) -> sj::Value {
+    let mut result = sj::Map::new();
+    for (category, lines) in sections {
+        let entries: Vec<sj::Value> = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Link {
+                    name, icon, groups, ..
+                } => {
+                    let mut entry = sj::Map::new();
+                    entry.insert("name".to_owned(), name.clone().into());
+                    entry.insert("icon".to_owned(), icon.clone().into());
+                    for (field, group) in category.group_fields(group_by).iter().zip(groups) {
+                        entry.insert(field.to_string(), group.clone().into());
+                    }
+                    Some(entry.into())
+                }
+                Line::Header { .. } => None,
+            })
+            .collect();
+        result.insert(category.as_str().to_owned(), entries.into());
+    }
+    result.into()
+}
+
+/// every name mapped to its icon url, per section; the first of repeated names wins
+pub fn to_manifest(sections: &[(Category, Vec<Line>)]) -> sj::Value {
+    let mut result = sj::Map::new();
+    for (category, lines) in sections {
+        let mut icons = sj::Map::new();
+        for line in lines {
+            if let Line::Link { name, icon, .. } = line {
+                icons
+                    .entry(name.clone())
+                    .or_insert_with(|| icon.clone().into());
+            }
+        }
+        result.insert(category.as_str().to_owned(), icons.into());
+    }
+    result.into()
+}
+
+/// a csv table per section, separated by blank lines
+pub fn to_csv(sections: &[(Category, Vec<Line>)], group_by: GroupBy) -> Vec<String> {
+    // rfc 4180: quote fields holding separators or quotes, doubling the quotes
+    let quote = |s: &str| {
+        if s.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    };
+    let mut result = Vec::new();
+    for (category, lines) in sections {
+        if !result.is_empty() {
+            result.push(String::new());
+        }
+        let name = match category {
+            Category::Buffs => "status",
+            _ => "name",
+        };
+        // flattened sections have no group columns
+        let grouped = lines
+            .iter()
+            .any(|line| matches!(line, Line::Link { groups, .. } if !groups.is_empty()));
+        let mut header = if grouped {
+            category.group_fields(group_by).to_vec()
+        } else {
+            Vec::new()
+        };
+        // deeper groups, such as trait tiers, have no column
+        let depth = header.len();
+        header.extend([name, "icon"]);
+        result.push(header.join(","));
+        for line in lines {
+            if let Line::Link {
+                name, icon, groups, ..
+            } = line
+            {
+                let fields: Vec<String> = groups
+                    .iter()
+                    .take(depth)
+                    .chain([name, icon])
+                    .map(|field| quote(field))
+                    .collect();
+                result.push(fields.join(","));
+            }
+        }
+    }
+    result
+}
+
+/// the names linking to each icon that more than one link points at
+///
+/// Markdown needs a definition per reference label, so these cannot be merged in the output.
+pub fn shared_icons(sections: &[(Category, Vec<Line>)]) -> BTreeMap<&str, Vec<&str>> {
+    let mut names: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for line in sections.iter().flat_map(|(_, lines)| lines) {
+        if let Line::Link { name, icon, .. } = line {
+            names.entry(icon).or_default().push(name);
+        }
+    }
+    names.retain(|_, names| names.len() > 1);
+    names
+}
+
+/// the name to icon mapping of a previous run, from its markdown output or its --manifest
+///
+/// Of repeated names the first wins, as in the manifest; markdown's numbered repeats such as
+/// `[Name (2)]` are recognized as such.
+pub fn parse_previous(text: &str) -> BTreeMap<String, String> {
+    let mut result = BTreeMap::new();
+    if let Ok(sj::Value::Object(sections)) = sj::from_str::<sj::Value>(text) {
+        for icons in sections.values().filter_map(|icons| icons.as_object()) {
+            for (name, icon) in icons {
+                if let Some(icon) = icon.as_str() {
+                    result
+                        .entry(name.clone())
+                        .or_insert_with(|| icon.to_owned());
+                }
+            }
+        }
+        return result;
+    }
+    let mut seen = HashSet::new();
+    for line in text.lines() {
+        let (inline, rest) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let Some((label, rest)) = rest.strip_prefix('[').and_then(split_label) else {
+            continue;
+        };
+        let url = match (inline, rest.strip_prefix(": "), rest.strip_prefix('(')) {
+            (false, Some(url), _) => url.split(' ').next(),
+            (true, _, Some(url)) => url.split([' ', ')']).next(),
+            _ => None,
+        };
+        let Some(url) = url.filter(|url| !url.is_empty()) else {
+            continue;
+        };
+        let repeat = label
+            .strip_suffix(')')
+            .and_then(|label| label.rsplit_once(" ("))
+            .filter(|(base, n)| n.parse::<u32>().is_ok() && seen.contains(&base.to_lowercase()));
+        if repeat.is_none() && seen.insert(label.to_lowercase()) {
+            result.insert(label, url.to_owned());
+        }
+    }
+    result
+}
+
+/// a label up to its unescaped closing bracket, and what follows the bracket
+fn split_label(text: &str) -> Option<(String, &str)> {
+    let mut label = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => label.extend(chars.next().map(|(_, c)| c)),
+            ']' => return Some((label, &text[i + 1..])),
+            _ => label.push(c),
+        }
+    }
+    None
+}
+
+/// the links added or given another icon since `previous`, then those no longer there
+pub fn diff_lines(
+    previous: &BTreeMap<String, String>,
+    sections: &[(Category, Vec<Line>)],
+) -> Vec<Line> {
+    let mut fresh = HashMap::new();
+    let mut changed = Vec::new();
+    for line in sections.iter().flat_map(|(_, lines)| lines) {
+        if let Line::Link { name, icon, .. } = line {
+            if fresh.insert(name.as_str(), icon).is_none() && previous.get(name) != Some(icon) {
+                changed.push((name.clone(), icon.clone()));
+            }
+        }
+    }
+    let mut removed: Vec<(String, String)> = previous
+        .iter()
+        .filter(|(name, _)| !fresh.contains_key(name.as_str()))
+        .map(|(name, icon)| (name.clone(), icon.clone()))
+        .collect();
+    removed.sort_by_cached_key(|(name, _)| collation_key(name));
+
+    let mut result = vec![Line::header(1, "Changes")];
+    for (title, links) in [("Added / Changed", changed), ("Removed", removed)] {
+        if links.is_empty() {
+            continue;
+        }
+        result.push(Line::header(2, title));
+        result.extend(links.into_iter().map(|(name, icon)| Line::Link {
+            name,
+            icon,
+            groups: vec![title.to_owned()],
+            title: None,
+        }));
+    }
+    result
+}
+
+/// how many links each section holds and how many groups they fall into
+pub fn counts(sections: &[(Category, Vec<Line>)]) -> String {
+    let mut result = Vec::new();
+    for (category, lines) in sections {
+        let mut links = 0;
+        let mut groups = HashSet::new();
+        for line in lines {
+            if let Line::Link { groups: g, .. } = line {
+                links += 1;
+                groups.insert(g);
+            }
+        }
+        result.push(match category.group_fields(GroupBy::Type) {
+            [] => format!("{}: {links}", category.as_str()),
+            _ => format!("{}: {links} in {} groups", category.as_str(), groups.len()),
+        });
+    }
+    result.join(", ")
+}
+
+/// the sections split into one page per profession, keyed by its lowercased name
+///
+/// Sections not grouped by profession get a page of their own, keyed by section name.
+pub fn split_by_profession(
+    sections: Vec<(Category, Vec<Line>)>,
+) -> BTreeMap<String, Vec<(Category, Vec<Line>)>> {
+    let mut pages: BTreeMap<String, Vec<(Category, Vec<Line>)>> = BTreeMap::new();
+    for (category, lines) in sections {
+        if category.group_fields(GroupBy::Type).first() != Some(&"profession") {
+            pages
+                .entry(category.as_str().to_owned())
+                .or_default()
+                .push((category, lines));
+            continue;
+        }
+        let mut title = None;
+        let mut page = category.as_str().to_owned();
+        for line in lines {
+            match &line {
+                Line::Header { level: 1, .. } => {
+                    title = Some(line);
+                    continue;
+                }
+                Line::Header { level: 2, title } => page = title.to_lowercase(),
+                _ => {}
+            }
+            // every page repeats the section's own header
+            let sections = pages.entry(page.clone()).or_default();
+            if sections.last().map(|(c, _)| *c) != Some(category) {
+                sections.push((category, title.iter().cloned().collect()));
+            }
+            sections.last_mut().unwrap().1.push(line);
+        }
+    }
+    pages
+}
+
+/// check that every link sits under the headers of its own groups
+pub fn validate_grouping(lines: &[&Line]) -> Result<()> {
+    let mut headers: Vec<&str> = Vec::new();
+    for line in lines {
+        match line {
+            Line::Header { level, title } => {
+                headers.truncate(level - 1);
+                headers.push(title);
+            }
+            Line::Link { name, groups, .. } => {
+                let current = headers.get(1..).unwrap_or_default();
+                if current != groups.as_slice() {
+                    return Err(Error::Validation(format!(
+                        "{name} is listed under {current:?} but belongs to {groups:?}"
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// buffs from `get_buffs`, grouped into boons, conditions and other effects
+///
+/// The api names buffs in the language asked for, so `lang` must be the one they came in.
+pub fn buffs_to_lines(buffs: BTreeMap<String, String>, lang: Lang) -> Result<Vec<Line>> {
+    let mut result = Vec::new();
+    result.push(Line::header(1, "Buffs"));
+    for kind in ["Boons", "Conditions", "Effects"] {
+        let buffs: Vec<_> = buffs
+            .iter()
+            .filter(|(status, _)| buff_kind(status, lang) == kind)
+            .collect();
+        if buffs.is_empty() {
+            continue;
+        }
+        result.push(Line::header(2, kind));
+        for (status, icon) in buffs {
+            result.push(Line::Link {
+                name: status.clone(),
+                icon: icon.clone(),
+                groups: vec![kind.to_owned()],
+                title: None,
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// the entries of grouped `lines` in one list sorted by name, under their section's header
+///
+/// An entry listed under several groups, such as a skill shared by professions, is kept once.
+pub fn flatten(lines: Vec<Line>) -> Vec<Line> {
+    let mut title = Vec::new();
+    let mut links = Vec::new();
+    for line in lines {
+        match line {
+            Line::Header { level: 1, .. } => title.push(line),
+            Line::Header { .. } => {}
+            Line::Link {
+                name, icon, title, ..
+            } => links.push((name, icon, title)),
+        }
+    }
+    links.sort_by_cached_key(|(name, icon, _)| (collation_key(name), icon.clone()));
+    links.dedup();
+    title.extend(links.into_iter().map(|(name, icon, title)| Line::Link {
+        name,
+        icon,
+        groups: Vec::new(),
+        title,
+    }));
+    title
+}
+
+/// titles and groups this crate names itself, with their spanish, german, french and chinese
+///
+/// Names the api gives, such as those of skills and specializations, already arrive in the
+/// requested language; professions, skill types and rarities are ids, so they are listed here.
+const LABELS: &[(&str, [&str; 4])] = &[
+    // sections
+    (
+        "Buffs",
+        [
+            "Efectos de estado",
+            "Statuseffekte",
+            "Effets de statut",
+            "状态效果",
+        ],
+    ),
+    (
+        "Skill",
+        ["Habilidades", "Fertigkeiten", "Compétences", "技能"],
+    ),
+    ("Traits", ["Rasgos", "Eigenschaften", "Aptitudes", "特性"]),
+    ("Items", ["Objetos", "Gegenstände", "Objets", "物品"]),
+    ("Pets", ["Mascotas", "Tiergefährten", "Familiers", "宠物"]),
+    (
+        "PvP Amulets",
+        [
+            "Amuletos de JcJ",
+            "PvP-Amulette",
+            "Amulettes JcJ",
+            "PvP护符",
+        ],
+    ),
+    ("Currencies", ["Divisas", "Währungen", "Monnaies", "货币"]),
+    (
+        "Minis",
+        ["Miniaturas", "Miniaturen", "Miniatures", "迷你宠物"],
+    ),
+    (
+        "Changes",
+        ["Cambios", "Änderungen", "Modifications", "变更"],
+    ),
+    (
+        "Added / Changed",
+        [
+            "Añadidos / Cambiados",
+            "Hinzugefügt / Geändert",
+            "Ajoutés / Modifiés",
+            "新增 / 变更",
+        ],
+    ),
+    ("Removed", ["Eliminados", "Entfernt", "Retirés", "移除"]),
+    // buff kinds
+    ("Boons", ["Bendiciones", "Segen", "Avantages", "增益"]),
+    (
+        "Conditions",
+        ["Condiciones", "Zustände", "Altérations", "症状"],
+    ),
+    ("Effects", ["Efectos", "Effekte", "Effets", "效果"]),
+    // professions
+    ("Guardian", ["Guardián", "Wächter", "Gardien", "守护者"]),
+    ("Warrior", ["Guerrero", "Krieger", "Guerrier", "战士"]),
+    (
+        "Engineer",
+        ["Ingeniero", "Ingenieur", "Ingénieur", "工程师"],
+    ),
+    ("Ranger", ["Guardabosques", "Waldläufer", "Rôdeur", "游侠"]),
+    ("Thief", ["Ladrón", "Dieb", "Voleur", "潜行者"]),
+    (
+        "Elementalist",
+        [
+            "Elementalista",
+            "Elementarmagier",
+            "Élémentaliste",
+            "元素使",
+        ],
+    ),
+    ("Mesmer", ["Hipnotizador", "Mesmer", "Envoûteur", "幻术师"]),
+    (
+        "Necromancer",
+        ["Nigromante", "Nekromant", "Nécromant", "死灵法师"],
+    ),
+    (
+        "Revenant",
+        ["Retornado", "Widergänger", "Revenant", "魂武者"],
+    ),
+    ("Unknown", ["Desconocida", "Unbekannt", "Inconnue", "未知"]),
+    // skill types
+    ("Weapon", ["Arma", "Waffe", "Arme", "武器"]),
+    ("Heal", ["Curación", "Heilung", "Soins", "治疗"]),
+    ("Utility", ["Apoyo", "Hilfe", "Utilitaire", "辅助"]),
+    ("Elite", ["Élite", "Elite", "Élite", "精英"]),
+    ("Profession", ["Profesión", "Klasse", "Profession", "职业"]),
+    (
+        "Toolbelt",
+        [
+            "Cinturón de herramientas",
+            "Werkzeuggürtel",
+            "Ceinture à outils",
+            "工具腰带",
+        ],
+    ),
+    ("Bundle", ["Conjunto", "Bündel", "Paquet", "捆绑"]),
+    (
+        "Transform",
+        ["Transformación", "Verwandlung", "Transformation", "变形"],
+    ),
+    ("Pet", ["Mascota", "Tiergefährte", "Familier", "宠物"]),
+    ("Monster", ["Monstruo", "Monster", "Monstre", "怪物"]),
+    // trait tiers
+    ("Minor", ["Menor", "Klein", "Mineur", "次要"]),
+    ("Adept", ["Adepto", "Adept", "Adepte", "熟练"]),
+    ("Master", ["Maestro", "Meister", "Maître", "大师"]),
+    (
+        "Grandmaster",
+        ["Gran maestro", "Großmeister", "Grand maître", "宗师"],
+    ),
+    ("Other", ["Otros", "Andere", "Autres", "其他"]),
+    // item rarities
+    ("Junk", ["Basura", "Schrott", "Déchet", "垃圾"]),
+    ("Basic", ["Básico", "Einfach", "Simple", "基础"]),
+    ("Fine", ["Bueno", "Edel", "Raffiné", "精良"]),
+    (
+        "Masterwork",
+        ["Obra maestra", "Meisterwerk", "Chef-d'œuvre", "杰作"],
+    ),
+    ("Rare", ["Excepcional", "Selten", "Rare", "稀有"]),
+    ("Exotic", ["Exótico", "Exotisch", "Exotique", "异域"]),
+    ("Ascended", ["Ascendido", "Aufgestiegen", "Élevé", "升华"]),
+    (
+        "Legendary",
+        ["Legendario", "Legendär", "Légendaire", "传奇"],
+    ),
+];
+
+/// where `lang` is found in the translations of `LABELS`, `BOONS` and `CONDITIONS`
+fn column(lang: Lang) -> Option<usize> {
+    match lang {
+        Lang::En => None,
+        Lang::Es => Some(0),
+        Lang::De => Some(1),
+        Lang::Fr => Some(2),
+        Lang::Zh => Some(3),
+    }
+}
+
+/// `text` in `lang` when it is one of `LABELS`, else unchanged
+fn translate(text: &str, lang: Lang) -> &str {
+    let Some(column) = column(lang) else {
+        return text;
+    };
+    LABELS
+        .iter()
+        .find(|(english, _)| *english == text)
+        .map_or(text, |(_, translated)| translated[column])
+}
+
+/// the buffs counted as boons, by their english name and as the api spells them in spanish,
+/// german, french and chinese
+const BOONS: &[(&str, [&str; 4])] = &[
+    ("Aegis", ["Égida", "Aegis", "Égide", "庇护"]),
+    ("Alacrity", ["Alacridad", "Alacrität", "Alacrité", "敏捷"]),
+    ("Fury", ["Furia", "Wut", "Furie", "狂怒"]),
+    ("Might", ["Poderío", "Macht", "Pouvoir", "威能"]),
+    ("Protection", ["Protección", "Schutz", "Protection", "保护"]),
+    (
+        "Quickness",
+        ["Celeridad", "Schnelligkeit", "Célérité", "急速"],
+    ),
+    (
+        "Regeneration",
+        ["Regeneración", "Regeneration", "Régénération", "再生"],
+    ),
+    (
+        "Resistance",
+        ["Resistencia", "Widerstand", "Résistance", "抗性"],
+    ),
+    (
+        "Resolution",
+        ["Resolución", "Entschlossenheit", "Résolution", "决心"],
+    ),
+    (
+        "Stability",
+        ["Estabilidad", "Stabilität", "Stabilité", "稳固"],
+    ),
+    ("Swiftness", ["Rapidez", "Eile", "Rapidité", "迅捷"]),
+    ("Vigor", ["Vigor", "Elan", "Vigueur", "活力"]),
+];
+
+/// the buffs counted as conditions, laid out as `BOONS`
+const CONDITIONS: &[(&str, [&str; 4])] = &[
+    ("Bleeding", ["Sangrado", "Blutung", "Saignement", "流血"]),
+    ("Blinded", ["Ceguera", "Blind", "Aveuglement", "致盲"]),
+    ("Burning", ["Quemadura", "Brennen", "Brûlure", "燃烧"]),
+    ("Chilled", ["Frío", "Unterkühlt", "Givre", "寒冷"]),
+    ("Confusion", ["Confusión", "Konfusion", "Confusion", "混乱"]),
+    ("Crippled", ["Tullido", "Verkrüppelt", "Infirmité", "残废"]),
+    ("Fear", ["Miedo", "Furcht", "Peur", "恐惧"]),
+    (
+        "Immobile",
+        ["Inmovilizado", "Bewegungsunfähig", "Immobilisation", "定身"],
+    ),
+    (
+        "Poisoned",
+        ["Envenenamiento", "Vergiftung", "Empoisonnement", "中毒"],
+    ),
+    ("Slow", ["Lentitud", "Langsamkeit", "Lenteur", "缓慢"]),
+    (
+        "Taunt",
+        ["Provocación", "Verhöhnung", "Provocation", "嘲讽"],
+    ),
+    ("Torment", ["Tormento", "Qual", "Tourment", "折磨"]),
+    (
+        "Vulnerability",
+        ["Vulnerabilidad", "Verwundbarkeit", "Vulnérabilité", "脆弱"],
+    ),
+    ("Weakness", ["Debilidad", "Schwäche", "Faiblesse", "虚弱"]),
+];
+
+/// the group a buff named in `lang` is listed under: boons, conditions, or any other effect
+fn buff_kind(status: &str, lang: Lang) -> &'static str {
+    let listed = |table: &[(&str, [&str; 4])]| {
+        table
+            .iter()
+            .any(|(english, translated)| match column(lang) {
+                None => *english == status,
+                Some(column) => translated[column] == status,
+            })
+    };
+    if listed(BOONS) {
+        "Boons"
+    } else if listed(CONDITIONS) {
+        "Conditions"
+    } else {
+        "Effects"
+    }
+}
+
+/// what this crate writes around names and numbers, `{}` standing for each in turn, with
+/// the spanish, german, french and chinese of it
+const PHRASES: &[(&str, [&str; 4])] = &[
+    (
+        "{} (Elite)",
+        ["{} (élite)", "{} (Elite)", "{} (élite)", "{}（精英）"],
+    ),
+    (
+        "Specialization {}",
+        [
+            "Especialización {}",
+            "Spezialisierung {}",
+            "Spécialisation {}",
+            "特性线 {}",
+        ],
+    ),
+    ("Tier {}", ["Nivel {}", "Stufe {}", "Rang {}", "第 {} 阶"]),
+    (
+        "{} (chain {})",
+        [
+            "{} (cadena {})",
+            "{} (Kette {})",
+            "{} (enchaînement {})",
+            "{}（连击 {}）",
+        ],
+    ),
+    (
+        "{} (flip of {})",
+        [
+            "{} (tras {})",
+            "{} (folgt auf {})",
+            "{} (après {})",
+            "{}（接续 {}）",
+        ],
+    ),
+];
+
+/// the words `text` puts into `pattern` where it has `{}`, when it has that shape
+fn fill_ins<'a>(pattern: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = pattern.split("{}");
+    let mut rest = text.strip_prefix(parts.next()?)?;
+    let mut words = Vec::new();
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        let (word, after) = match parts.peek() {
+            Some(_) => rest.split_at(rest.find(part).filter(|_| !part.is_empty())?),
+            None => (rest.strip_suffix(part)?, ""),
+        };
+        if word.is_empty() {
+            return None;
+        }
+        words.push(word);
+        rest = &after[part.len().min(after.len())..];
+    }
+    Some(words)
+}
+
+/// `text` in `lang` when it is one of `PHRASES`, the words put into it left as they are
+fn translate_phrase(text: &str, lang: Lang) -> Option<String> {
+    let column = column(lang)?;
+    PHRASES.iter().find_map(|(english, translated)| {
+        let words = fill_ins(english, text)?;
+        let mut result = translated[column].to_owned();
+        for word in words {
+            result = result.replacen("{}", word, 1);
+        }
+        Some(result)
+    })
+}
+
+/// the headers of `lines`, the groups of their links and what this crate added to link names
+/// in `lang`, so headers and groups still agree
+pub fn localize(lines: &mut [Line], lang: Lang) {
+    let label = |text: &str| {
+        translate_phrase(text, lang).unwrap_or_else(|| translate(text, lang).to_owned())
+    };
+    for line in lines {
+        match line {
+            Line::Header { title, .. } => *title = label(title),
+            Line::Link { name, groups, .. } => {
+                if let Some(translated) = translate_phrase(name, lang) {
+                    *name = translated;
+                }
+                for group in groups {
+                    *group = label(group);
+                }
+            }
+        }
+    }
+}
+
+/// a `title` header over every entry of `json` that has an icon, sorted by name
+pub fn links_to_lines(json: sj::Value, title: &str) -> Result<Vec<Line>> {
+    let mut entries = json
+        .as_array()
+        .or_shape("not an array")?
+        .iter()
+        .map(|entry| {
+            let name = entry.get("name").and_then(|v| v.as_str());
+            let name = name.ok_or_else(|| Error::shape(format!("{title} entry has no name")))?;
+            let icon = entry.get("icon").and_then(|v| v.as_str());
+            let kept = has_icon(&title.to_lowercase(), name, icon);
+            Ok(kept.then(|| (name.to_owned(), icon.unwrap_or_default().to_owned())))
+        })
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by_key(|(name, _)| collation_key(name));
+
+    let mut result = vec![Line::header(1, title)];
+    for (name, icon) in entries {
+        result.push(Line::Link {
+            name,
+            icon,
+            groups: Vec::new(),
+            title: None,
+        });
+    }
+    Ok(result)
+}
+
+/// item rarities from lowest to highest, the order their groups are emitted in
+const RARITIES: [&str; 8] = [
+    "Junk",
+    "Basic",
+    "Fine",
+    "Masterwork",
+    "Rare",
+    "Exotic",
+    "Ascended",
+    "Legendary",
+];
+
+/// items grouped by rarity, lowest first
+pub fn items_to_lines(json: sj::Value) -> Result<Vec<Line>> {
+    let mut items = json
+        .as_array()
+        .or_shape("not an array")?
+        .iter()
+        .map(|item| {
+            let field = |k| {
+                item.get(k)
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| Error::shape(format!("item has no {k}")))
+            };
+            Ok((
+                field("rarity")?.to_owned(),
+                field("name")?.to_owned(),
+                field("icon")?.to_owned(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // rarities the list does not know yet go last
+    items.sort_by_key(|(rarity, name, _)| {
+        let rank = RARITIES.iter().position(|r| r == rarity);
+        (
+            rank.unwrap_or(RARITIES.len()),
+            rarity.clone(),
+            collation_key(name),
+        )
+    });
+
+    let mut last_rarity = String::new();
+    let mut result = vec![Line::header(1, "Items")];
+    for (rarity, name, icon) in items {
+        if rarity != last_rarity {
+            result.push(Line::header(2, &rarity));
+            last_rarity = rarity.clone();
+        }
+        result.push(Line::Link {
+            name,
+            icon,
+            groups: vec![rarity],
+            title: None,
+        });
+    }
+    Ok(result)
+}
+
+/// the playable professions, as the api names them
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Profession {
+    Elementalist,
+    Engineer,
+    Guardian,
+    Mesmer,
+    Necromancer,
+    Ranger,
+    Revenant,
+    Thief,
+    Warrior,
+}
+
+impl Profession {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profession::Elementalist => "Elementalist",
+            Profession::Engineer => "Engineer",
+            Profession::Guardian => "Guardian",
+            Profession::Mesmer => "Mesmer",
+            Profession::Necromancer => "Necromancer",
+            Profession::Ranger => "Ranger",
+            Profession::Revenant => "Revenant",
+            Profession::Thief => "Thief",
+            Profession::Warrior => "Warrior",
+        }
+    }
+}
+
+/// whether `prof` passes a profession filter, where an empty filter passes everything
+fn wanted(professions: &[Profession], prof: &str) -> bool {
+    professions.is_empty() || professions.iter().any(|p| prof == p.as_str())
+}
+
+/// sort key reading naturally in every language: case and accents only break ties
+fn collation_key(name: &str) -> (String, String) {
+    let base = name
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+    (base, name.to_owned())
+}
+
+/// a description as a link title: on one line, without the game's `<c=@...>` color tags
+fn description(text: Option<&str>) -> Option<String> {
+    let mut text = text?;
+    let mut plain = String::new();
+    while let Some(start) = text.find('<') {
+        plain.push_str(&text[..start]);
+        let tag = &text[start..];
+        let color = ["<c=", "<c>", "</c>"].iter().any(|c| tag.starts_with(c));
+        match tag.find('>') {
+            Some(end) if color => text = &tag[end + 1..],
+            _ => {
+                plain.push('<');
+                text = &tag[1..];
+            }
+        }
+    }
+    plain.push_str(text);
+    let words: Vec<&str> = plain.split_whitespace().collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// whether an entry has an icon to link to, warning about it when not
+fn has_icon(kind: &str, name: &str, icon: Option<&str>) -> bool {
+    if icon.is_none() {
+        log::warn!("skipped {kind} {name}: no icon");
+    }
+    icon.is_some()
+}
+
+/// where a skill sits in a chain or flip-over
+struct ChainStep {
+    /// name of the skill the chain starts from
+    starter: String,
+    /// 0 for the starter itself
+    position: usize,
+    /// appended to the skill's name, e.g. " (chain 2/3)"
+    suffix: String,
+}
+
+/// the chained and flipped-to skills among `skills` by id, empty unless `shrink_skills` kept
+/// their links
+fn chain_steps(skills: &[Skill]) -> HashMap<u64, ChainStep> {
+    let by_id: BTreeMap<u64, &Skill> = skills.iter().map(|skill| (skill.id, skill)).collect();
+    // links to skills that were dropped are ignored
+    let link = |id: Option<u64>| id.filter(|id| by_id.contains_key(id));
+    let name = |id: u64| by_id[&id].name.clone();
+
+    let mut result = HashMap::new();
+    for (&id, skill) in &by_id {
+        if link(skill.prev_chain).is_some() || link(skill.next_chain).is_none() {
+            continue;
+        }
+        let mut chain = vec![id];
+        while let Some(next) = link(by_id[chain.last().unwrap()].next_chain) {
+            if chain.contains(&next) {
+                break;
+            }
+            chain.push(next);
+        }
+        for (position, &step) in chain.iter().enumerate() {
+            let suffix = format!(" (chain {}/{})", position + 1, chain.len());
+            let starter = name(id);
+            result.insert(
+                step,
+                ChainStep {
+                    starter,
+                    position,
+                    suffix,
+                },
+            );
+        }
+    }
+    // of two skills flipping into each other, the one with the lower id is the starter
+    let mut flipped = HashSet::new();
+    for (&id, skill) in &by_id {
+        let Some(flip) = link(skill.flip_skill) else {
+            continue;
+        };
+        if flipped.contains(&id) || result.contains_key(&flip) {
+            continue;
+        }
+        flipped.insert(flip);
+        let (starter, position) = match result.get(&id) {
+            Some(step) => (step.starter.clone(), step.position + 1),
+            None => (name(id), 1),
+        };
+        let suffix = format!(" (flip of {})", name(id));
+        result.insert(
+            flip,
+            ChainStep {
+                starter,
+                position,
+                suffix,
+            },
+        );
+    }
+    result
+}
+
+/// skills sorted by `sort`, by name unless given
+pub fn skills_to_lines(
+    skills: &[Skill],
+    professions: &[Profession],
+    group_by: GroupBy,
+    sort: Option<SortBy>,
+) -> Result<Vec<Line>> {
+    let steps = chain_steps(skills);
+    let step = |skill: &Skill| steps.get(&skill.id);
+    let mut skills: Vec<(&str, &Skill)> = skills
+        .iter()
+        .filter(|skill| has_icon("skill", &skill.name, skill.icon.as_deref()))
+        // a skill shared by several professions is listed under each of them
+        .flat_map(|skill| {
+            let shared = skill.professions.iter();
+            shared.map(move |prof| (prof.as_str(), skill))
+        })
+        .filter(|(prof, _)| wanted(professions, prof))
+        .collect();
+    // not every skill has a slot, those fall back to their type
+    let group = |skill: &Skill| {
+        let slot = match group_by {
+            GroupBy::Type => None,
+            GroupBy::Slot => skill.slot.as_deref(),
+        };
+        slot.or(skill.kind.as_deref())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    // chained and flipped-to skills follow the skill they start from
+    skills.sort_by_key(|&(prof, skill)| {
+        let name = skill.name.as_str();
+        let (starter, position) = step(skill).map_or((name, 0), |s| (&s.starter, s.position));
+        // skills without a slot go last
+        let slot = match sort {
+            Some(SortBy::Ingame) => skill.slot.as_deref(),
+            Some(SortBy::Name) | None => Some(""),
+        };
+        (
+            prof,
+            group(skill),
+            (slot.is_none(), slot.map(str::to_owned)),
+            collation_key(starter),
+            position,
+            collation_key(name),
+        )
+    });
+
+    let (mut last_prof, mut last_type) = ("", "".to_owned());
+    let mut result = Vec::new();
+    result.push(Line::header(1, "Skill"));
+    for (prof, skill) in skills {
+        let typ = group(skill);
+        let groups = vec![prof.to_owned(), typ.clone()];
+        if prof != last_prof {
+            last_prof = prof;
+            last_type = typ;
+            result.push(Line::header(2, last_prof));
+            result.push(Line::header(3, &last_type));
+        } else if typ != last_type {
+            last_type = typ;
+            result.push(Line::header(3, &last_type));
+        }
+
+        let suffix = step(skill).map_or("", |s| &s.suffix);
+        result.push(Line::Link {
+            name: format!("{}{suffix}", skill.name),
+            icon: skill.icon.clone().unwrap_or_default(),
+            groups,
+            title: description(skill.description.as_deref()),
+        });
+    }
+    Ok(result)
+}
+
+/// what happens to traits of a specialization the api did not list
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum UnknownSpecs {
+    /// leave them out, with a warning
+    #[default]
+    Skip,
+    /// list them under an "Unknown" profession
+    Bucket,
+    /// stop with an error naming the specialization
+    Fail,
+}
+
+/// a trait with the profession, header and icon of its specialization
+struct Placed<'a> {
+    profession: String,
+    spec: String,
+    spec_icon: Option<String>,
+    t: &'a Trait,
+}
+
+/// the trait panel row of a trait: its rank and header, minor traits coming first
+fn trait_tier(t: &Trait) -> (u64, String) {
+    if t.slot.as_deref() == Some("Minor") {
+        return (0, "Minor".to_owned());
+    }
+    match t.tier {
+        Some(1) => (1, "Adept".to_owned()),
+        Some(2) => (2, "Master".to_owned()),
+        Some(3) => (3, "Grandmaster".to_owned()),
+        Some(n) => (n, format!("Tier {n}")),
+        None => (u64::MAX, "Other".to_owned()),
+    }
+}
+
+/// traits sorted by `sort`, in game order unless given
+///
+/// With `by_tier`, each specialization's traits are grouped under Minor, Adept, Master and
+/// Grandmaster headers, as in the in-game trait panel.
+pub fn traits_to_lines(
+    traits: &[Trait],
+    spec_map: HashMap<i32, Specialization>,
+    professions: &[Profession],
+    spec_icons: bool,
+    by_tier: bool,
+    unknown: UnknownSpecs,
+    sort: Option<SortBy>,
+) -> Result<Vec<Line>> {
+    let mut placed = Vec::new();
+    for t in traits {
+        if !has_icon("trait", &t.name, t.icon.as_deref()) {
+            continue;
+        }
+        let s = t.specialization;
+        // right after a patch, traits can point at specializations not listed yet
+        let (profession, spec, spec_icon) = match (spec_map.get(&s), unknown) {
+            (Some(spec), _) if spec.elite => (
+                spec.profession.clone(),
+                format!("{} (Elite)", spec.name),
+                spec.icon.clone(),
+            ),
+            (Some(spec), _) => (
+                spec.profession.clone(),
+                spec.name.clone(),
+                spec.icon.clone(),
+            ),
+            (None, UnknownSpecs::Skip) => {
+                log::warn!("skipped trait {}: unknown specialization {s}", t.name);
+                continue;
+            }
+            (None, UnknownSpecs::Bucket) => {
+                ("Unknown".to_owned(), format!("Specialization {s}"), None)
+            }
+            (None, UnknownSpecs::Fail) => {
+                return Err(Error::MissingSpecialization { id: s.into() });
+            }
+        };
+        if wanted(professions, &profession) {
+            placed.push(Placed {
+                profession,
+                spec,
+                spec_icon,
+                t,
+            });
+        }
+    }
+    // within a specialization, follow the in-game trait tree; traits without a place go last
+    let place = |value: Option<u64>| match sort {
+        Some(SortBy::Ingame) | None => value,
+        Some(SortBy::Name) => Some(0),
+    };
+    placed.sort_by_key(|x| {
+        (
+            x.profession.clone(),
+            x.spec.clone(),
+            if by_tier { trait_tier(x.t).0 } else { 0 },
+            place(x.t.tier).unwrap_or(u64::MAX),
+            place(x.t.order).unwrap_or(u64::MAX),
+            collation_key(&x.t.name),
+        )
+    });
+
+    let (mut last_prof, mut last_spec) = ("".to_owned(), "".to_owned());
+    let mut last_tier = String::new();
+    let mut result = Vec::new();
+    result.push(Line::header(1, "Traits"));
+    for Placed {
+        profession: prof,
+        spec,
+        spec_icon,
+        t,
+    } in placed
+    {
+        let mut groups = vec![prof.clone(), spec.clone()];
+        let new_spec = prof != last_prof || spec != last_spec;
+        if prof != last_prof {
+            last_prof = prof;
+            last_spec = spec;
+            result.push(Line::header(2, &last_prof));
+            result.push(Line::header(3, &last_spec));
+        } else if spec != last_spec {
+            last_spec = spec;
+            result.push(Line::header(3, &last_spec));
+        }
+        // the specialization's own icon leads its traits
+        if let (true, true, Some(icon)) = (new_spec, spec_icons, spec_icon) {
+            result.push(Line::Link {
+                name: last_spec.clone(),
+                icon,
+                groups: groups.clone(),
+                title: None,
+            });
+        }
+        if by_tier {
+            let (_, tier) = trait_tier(t);
+            if new_spec || tier != last_tier {
+                result.push(Line::header(4, &tier));
+                last_tier = tier.clone();
+            }
+            groups.push(tier);
+        }
+
+        result.push(Line::Link {
+            name: t.name.clone(),
+            icon: t.icon.clone().unwrap_or_default(),
+            groups,
+            title: description(t.description.as_deref()),
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{shrink_skills, shrink_traits};
+
+    /// skills as `shrink_skills` hands them over, with their links and descriptions
+    fn parse_skills(json: sj::Value) -> Vec<Skill> {
+        shrink_skills(json, true, true, true).unwrap()
+    }
+
+    fn parse_traits(json: sj::Value) -> Vec<Trait> {
+        shrink_traits(json, true).unwrap()
+    }
+
+    /// a link without a title
+    fn link(name: &str, icon: &str, groups: &[&str]) -> Line {
+        Line::Link {
+            name: name.to_owned(),
+            icon: icon.to_owned(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            title: None,
+        }
+    }
+
+    /// a core warrior specialization, id 4
+    fn strength() -> Specialization {
+        Specialization {
+            id: 4,
+            profession: "Warrior".to_owned(),
+            name: "Strength".to_owned(),
+            elite: false,
+            icon: None,
+        }
+    }
+
+    #[test]
+    fn escape_label_brackets() {
+        let link = link(
+            "Signet of [Mercy]",
+            "https://render.guildwars2.com/file/0/1.png",
+            &[],
+        );
+        assert_eq!(
+            to_markdown(&[&link], LinkStyle::Reference),
+            [r"[Signet of \[Mercy\]]: https://render.guildwars2.com/file/0/1.png"]
+        );
+        assert_eq!(
+            to_markdown(&[&link], LinkStyle::Inline),
+            [r"![Signet of \[Mercy\]](https://render.guildwars2.com/file/0/1.png)"]
+        );
+        assert_eq!(escape_label(r"a\b"), r"a\\b");
+    }
+
+    #[test]
+    fn to_markdown_disambiguates_repeated_names() {
+        let lines = [
+            Line::header(3, "Weapon"),
+            link("Dragon's Tooth", "a.png", &["Elementalist", "Weapon"]),
+            link("Dragon's Tooth", "b.png", &["Elementalist", "Weapon"]),
+            link("dragon's tooth", "c.png", &["Elementalist", "Weapon"]),
+        ];
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "### Weapon",
+                "[Dragon's Tooth]: a.png",
+                "[Dragon's Tooth (2)]: b.png",
+                "[dragon's tooth (3)]: c.png",
+            ]
+        );
+        assert_eq!(
+            to_markdown(&lines[1..3], LinkStyle::Inline),
+            ["![Dragon's Tooth](a.png)", "![Dragon's Tooth](b.png)"]
+        );
+    }
+
+    #[test]
+    fn to_markdown_part_disambiguates_across_parts() {
+        let link = link("Fireball", "a.png", &[]);
+        let mut used = HashSet::new();
+        let first = to_markdown_part(&[&link], LinkStyle::Reference, &mut used);
+        let second = to_markdown_part(&[&link], LinkStyle::Reference, &mut used);
+        assert_eq!(first, ["[Fireball]: a.png"]);
+        assert_eq!(second, ["[Fireball (2)]: a.png"]);
+    }
+
+    #[test]
+    fn to_manifest_keeps_first_icon() {
+        let sections = [
+            (Category::Buffs, vec![link("Might", "might.png", &[])]),
+            (
+                Category::Skills,
+                vec![
+                    Line::header(1, "Skill"),
+                    link("Dragon's Tooth", "a.png", &[]),
+                    link("Dragon's Tooth", "b.png", &[]),
+                ],
+            ),
+        ];
+        assert_eq!(
+            to_manifest(&sections),
+            sj::json!({
+                "buffs": { "Might": "might.png" },
+                "skills": { "Dragon's Tooth": "a.png" }
+            })
+        );
+    }
+
+    #[test]
+    fn skills_to_lines_groups_by_slot() {
+        let skills = sj::json!([
+            {
+                "id": 1,
+                "name": "Signet of Restoration",
+                "icon": "a.png",
+                "type": "Heal",
+                "slot": "Heal",
+                "professions": ["Elementalist"]
+            },
+            {
+                "id": 2,
+                "name": "Fireball",
+                "icon": "b.png",
+                "type": "Weapon",
+                "slot": "Weapon_1",
+                "professions": ["Elementalist"]
+            },
+            {
+                "id": 3,
+                "name": "Overload Fire",
+                "icon": "c.png",
+                "type": "Profession",
+                "professions": ["Elementalist"]
+            }
+        ]);
+        let lines = skills_to_lines(&parse_skills(skills), &[], GroupBy::Slot, None).unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Skill",
+                "## Elementalist",
+                "### Heal",
+                "[Signet of Restoration]: a.png",
+                "### Profession",
+                "[Overload Fire]: c.png",
+                "### Weapon_1",
+                "[Fireball]: b.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn skills_to_lines_sorts_by_slot_in_game() {
+        let skill = |id: u64, name: &str, slot: &str| {
+            sj::json!({
+                "id": id,
+                "name": name,
+                "icon": "s.png",
+                "type": "Weapon",
+                "slot": slot,
+                "professions": ["Guardian"]
+            })
+        };
+        let skills = sj::json!([
+            skill(1, "Zealot's Defense", "Weapon_4"),
+            skill(2, "Symbol of Swiftness", "Weapon_2"),
+            skill(3, "Orb of Wrath", "Weapon_1"),
+        ]);
+        let lines = skills_to_lines(
+            &parse_skills(skills),
+            &[],
+            GroupBy::Type,
+            Some(SortBy::Ingame),
+        )
+        .unwrap();
+        let names: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Link { name, .. } => Some(name.as_str()),
+                Line::Header { .. } => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            ["Orb of Wrath", "Symbol of Swiftness", "Zealot's Defense"]
+        );
+    }
+
+    #[test]
+    fn skills_to_lines_follows_chains() {
+        let skill = |id: u64, name: &str, links: sj::Value| {
+            let mut skill = sj::json!({
+                "id": id,
+                "name": name,
+                "icon": format!("{id}.png"),
+                "type": "Weapon",
+                "professions": ["Warrior"]
+            });
+            skill
+                .as_object_mut()
+                .unwrap()
+                .extend(links.as_object().unwrap().clone());
+            skill
+        };
+        let skills = sj::json!([
+            skill(3, "Brutal Strike", sj::json!({ "prev_chain": 2 })),
+            skill(1, "Sever Artery", sj::json!({ "next_chain": 2 })),
+            skill(2, "Gash", sj::json!({ "prev_chain": 1, "next_chain": 3 })),
+            skill(4, "Arcing Slice", sj::json!({})),
+            skill(5, "Whirlwind Attack", sj::json!({ "flip_skill": 6 })),
+            skill(6, "Bladetrail", sj::json!({ "flip_skill": 5 })),
+        ]);
+        let lines = skills_to_lines(&parse_skills(skills), &[], GroupBy::Type, None).unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Skill",
+                "## Warrior",
+                "### Weapon",
+                "[Arcing Slice]: 4.png",
+                "[Sever Artery (chain 1/3)]: 1.png",
+                "[Gash (chain 2/3)]: 2.png",
+                "[Brutal Strike (chain 3/3)]: 3.png",
+                "[Whirlwind Attack]: 5.png",
+                "[Bladetrail (flip of Whirlwind Attack)]: 6.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_lines_sorts_by_name() {
+        let currencies = sj::json!([
+            { "name": "Spirit Shard", "icon": "b.png" },
+            { "name": "Karma", "icon": "a.png" }
+        ]);
+        let lines = links_to_lines(currencies, "Currencies").unwrap();
+        assert_eq!(
+            to_markdown(&lines.iter().collect::<Vec<_>>(), LinkStyle::Reference),
+            ["# Currencies", "[Karma]: a.png", "[Spirit Shard]: b.png"]
+        );
+    }
+
+    #[test]
+    fn links_to_lines_skips_missing_icon() {
+        let minis = sj::json!([
+            { "name": "Mini Rytlock", "icon": "a.png" },
+            { "name": "Mini Iconless" }
+        ]);
+        let lines = links_to_lines(minis, "Minis").unwrap();
+        assert_eq!(
+            to_markdown(&lines.iter().collect::<Vec<_>>(), LinkStyle::Reference),
+            ["# Minis", "[Mini Rytlock]: a.png"]
+        );
+        assert!(links_to_lines(sj::json!([{ "icon": "a.png" }]), "Minis").is_err());
+    }
+
+    #[test]
+    fn buffs_to_lines_in_german() {
+        let buffs = BTreeMap::from([
+            ("Macht".to_owned(), "m.png".to_owned()),
+            ("Brennen".to_owned(), "b.png".to_owned()),
+            ("Ansturm".to_owned(), "a.png".to_owned()),
+            // the english name is no boon in german output
+            ("Might".to_owned(), "e.png".to_owned()),
+        ]);
+        let mut lines = buffs_to_lines(buffs, Lang::De).unwrap();
+        localize(&mut lines, Lang::De);
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Statuseffekte",
+                "## Segen",
+                "[Macht]: m.png",
+                "## Zustände",
+                "[Brennen]: b.png",
+                "## Effekte",
+                "[Ansturm]: a.png",
+                "[Might]: e.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn flatten_drops_groups_and_duplicates() {
+        let skills = sj::json!([
+            { "id": 1, "name": "Signet of Rage", "icon": "b.png", "type": "Elite",
+              "professions": ["Warrior"] },
+            { "id": 2, "name": "Arcing Slice", "icon": "a.png", "type": "Weapon",
+              "professions": ["Warrior", "Guardian"] }
+        ]);
+        let lines =
+            flatten(skills_to_lines(&parse_skills(skills), &[], GroupBy::Type, None).unwrap());
+        assert_eq!(
+            to_markdown(&lines.iter().collect::<Vec<_>>(), LinkStyle::Reference),
+            [
+                "# Skill",
+                "[Arcing Slice]: a.png",
+                "[Signet of Rage]: b.png"
+            ]
+        );
+        assert_eq!(
+            to_csv(&[(Category::Skills, lines)], GroupBy::Type),
+            ["name,icon", "Arcing Slice,a.png", "Signet of Rage,b.png"]
+        );
+    }
+
+    #[test]
+    fn localize_translates_own_labels_only() {
+        let skills = sj::json!([{
+            "id": 1,
+            "name": "Bénédiction de l'Élu",
+            "icon": "a.png",
+            "type": "Heal",
+            "professions": ["Guardian"]
+        }]);
+        let mut lines = skills_to_lines(&parse_skills(skills), &[], GroupBy::Type, None).unwrap();
+        localize(&mut lines, Lang::Fr);
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Compétences",
+                "## Gardien",
+                "### Soins",
+                "[Bénédiction de l'Élu]: a.png"
+            ]
+        );
+        assert!(validate_grouping(&lines).is_ok());
+        assert_eq!(table_of_contents(&lines)[1], "  - [Gardien](#gardien)");
+    }
+
+    #[test]
+    fn localize_translates_built_phrases() {
+        let mut lines = vec![
+            Line::header(2, "Warrior"),
+            Line::header(3, "Berserker (Elite)"),
+            Line::header(4, "Tier 4"),
+            link(
+                "Berserker (Elite)",
+                "a.png",
+                &["Warrior", "Berserker (Elite)", "Tier 4"],
+            ),
+            Line::header(2, "Unknown"),
+            Line::header(3, "Specialization 99"),
+            link(
+                "Gash (chain 2/3)",
+                "a.png",
+                &["Unknown", "Specialization 99"],
+            ),
+            link("Bladetrail (flip of Whirlwind Attack)", "a.png", &[]),
+            link("Eviscerate (Burst)", "a.png", &[]),
+        ];
+        localize(&mut lines, Lang::Fr);
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "## Guerrier",
+                "### Berserker (élite)",
+                "#### Rang 4",
+                "[Berserker (élite)]: a.png",
+                "## Inconnue",
+                "### Spécialisation 99",
+                "[Gash (enchaînement 2/3)]: a.png",
+                "[Bladetrail (après Whirlwind Attack)]: a.png",
+                "[Eviscerate (Burst)]: a.png",
+            ]
+        );
+        match lines[3] {
+            Line::Link { groups, .. } => {
+                assert_eq!(groups, &["Guerrier", "Berserker (élite)", "Rang 4"])
+            }
+            Line::Header { .. } => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn descriptions_become_link_titles() {
+        let skills = sj::json!([{
+            "id": 1,
+            "name": "Fireball",
+            "icon": "a.png",
+            "type": "Weapon",
+            "professions": ["Elementalist"],
+            "description": " Launch a <c=@abilitytype>\"fireball\"</c>\nat 3 < 4 foes. "
+        }]);
+        let lines = skills_to_lines(&parse_skills(skills), &[], GroupBy::Type, None).unwrap();
+        let link = lines.last().unwrap();
+        assert_eq!(
+            to_markdown(&[link], LinkStyle::Reference),
+            [r#"[Fireball]: a.png "Launch a \"fireball\" at 3 < 4 foes.""#]
+        );
+        assert_eq!(
+            to_markdown(&[link], LinkStyle::Inline),
+            [r#"![Fireball](a.png "Launch a \"fireball\" at 3 < 4 foes.")"#]
+        );
+    }
+
+    #[test]
+    fn diff_against_previous_markdown() {
+        let previous = parse_previous(
+            "<!-- build 1 -->\n# Skill\n## Warrior\n[Bolt]: old.png\n[Sword \\[1\\]]: s.png\n\
+             [bolt (2)]: other.png\n[Gone]: gone.png \"a title\"\n",
+        );
+        assert_eq!(
+            previous,
+            BTreeMap::from([
+                ("Bolt".to_owned(), "old.png".to_owned()),
+                ("Gone".to_owned(), "gone.png".to_owned()),
+                ("Sword [1]".to_owned(), "s.png".to_owned()),
+            ])
+        );
+        let sections = [(
+            Category::Skills,
+            vec![
+                link("Bolt", "new.png", &[]),
+                link("Sword [1]", "s.png", &[]),
+                link("Axe", "a.png", &[]),
+            ],
+        )];
+        let lines = diff_lines(&previous, &sections);
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Changes",
+                "## Added / Changed",
+                "[Bolt]: new.png",
+                "[Axe]: a.png",
+                "## Removed",
+                "[Gone]: gone.png",
+            ]
+        );
+        // a manifest gives the same mapping
+        let manifest = to_manifest(&sections).to_string();
+        assert_eq!(diff_lines(&parse_previous(&manifest), &sections).len(), 1);
+    }
+
+    #[test]
+    fn shared_icons_across_sections() {
+        let sections = [
+            (
+                Category::Buffs,
+                vec![
+                    link("Might", "might.png", &[]),
+                    link("Fury", "fury.png", &[]),
+                ],
+            ),
+            (
+                Category::Skills,
+                vec![link("\"For Great Justice!\"", "might.png", &[])],
+            ),
+        ];
+        assert_eq!(
+            shared_icons(&sections),
+            BTreeMap::from([("might.png", vec!["Might", "\"For Great Justice!\""])])
+        );
+    }
+
+    #[test]
+    fn to_anki_puts_the_icon_on_the_front() {
+        let lines = [
+            Line::header(2, "Elementalist"),
+            link("Fireball", "icons/5491.png", &["Elementalist", "Weapon"]),
+            link("Might", "https://render.guildwars2.com/file/M/1.png", &[]),
+        ];
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_anki(&lines),
+            [
+                "#separator:tab",
+                "#html:true",
+                "<img src=\"5491.png\">\tFireball<br>Elementalist / Weapon",
+                "<img src=\"https://render.guildwars2.com/file/M/1.png\">\tMight",
+            ]
+        );
+    }
+
+    #[test]
+    fn buffs_to_lines_groups_by_kind() {
+        let buffs = BTreeMap::from(
+            ["Burning", "Might", "Stun", "Vigor"].map(|s| (s.to_owned(), format!("{s}.png"))),
+        );
+        let lines = buffs_to_lines(buffs, Lang::En).unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Buffs",
+                "## Boons",
+                "[Might]: Might.png",
+                "[Vigor]: Vigor.png",
+                "## Conditions",
+                "[Burning]: Burning.png",
+                "## Effects",
+                "[Stun]: Stun.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn collation_key_ignores_case_and_accents() {
+        let mut names = ["Zorn", "Äther", "arkane Kraft", "Ather", "Eis"];
+        names.sort_by_key(|name| collation_key(name));
+        assert_eq!(names, ["arkane Kraft", "Ather", "Äther", "Eis", "Zorn"]);
+    }
+
+    #[test]
+    fn to_html_wraps_links_in_grids() {
+        let lines = [
+            Line::header(2, "Guardian"),
+            link("\"Save Yourselves!\"", "a.png", &[]),
+            Line::header(3, "Heal & Utility"),
+        ];
+        let lines: Vec<&Line> = lines.iter().collect();
+        let html = to_html(&lines);
+        let body = html.iter().position(|line| line == "<body>").unwrap();
+        assert_eq!(
+            html[body + 1..],
+            [
+                "<h2>Guardian</h2>",
+                "<div class=\"grid\">",
+                "<img src=\"a.png\" alt=\"&quot;Save Yourselves!&quot;\" \
+                 title=\"&quot;Save Yourselves!&quot;\">",
+                "</div>",
+                "<h3>Heal &amp; Utility</h3>",
+                "</body>",
+                "</html>",
+            ]
+        );
+    }
+
+    #[test]
+    fn items_to_lines_groups_by_rarity() {
+        let items = sj::json!([
+            { "name": "Frostfang", "icon": "a.png", "rarity": "Legendary" },
+            { "name": "Glob of Ectoplasm", "icon": "b.png", "rarity": "Exotic" },
+            { "name": "Eternity", "icon": "c.png", "rarity": "Legendary" }
+        ]);
+        let lines = items_to_lines(items).unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Items",
+                "## Exotic",
+                "[Glob of Ectoplasm]: b.png",
+                "## Legendary",
+                "[Eternity]: c.png",
+                "[Frostfang]: a.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn traits_to_lines_links_spec_icon() {
+        let traits = sj::json!([
+            { "name": "Vigorous Precision", "icon": "a.png", "specialization": 18 },
+            { "name": "Always Angry", "icon": "b.png", "specialization": 18 }
+        ]);
+        let spec = Specialization {
+            id: 18,
+            profession: "Warrior".to_owned(),
+            name: "Berserker".to_owned(),
+            elite: true,
+            icon: Some("spec.png".to_owned()),
+        };
+        let lines = traits_to_lines(
+            &parse_traits(traits),
+            HashMap::from([(18, spec)]),
+            &[],
+            true,
+            false,
+            UnknownSpecs::Skip,
+            None,
+        )
+        .unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Traits",
+                "## Warrior",
+                "### Berserker (Elite)",
+                "[Berserker (Elite)]: spec.png",
+                "[Always Angry]: b.png",
+                "[Vigorous Precision]: a.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_links_and_groups() {
+        let sections = [
+            (
+                Category::Buffs,
+                vec![Line::header(1, "Buffs"), link("Might", "", &["Boons"])],
+            ),
+            (
+                Category::Traits,
+                vec![
+                    link("a", "", &["Warrior", "Strength"]),
+                    link("b", "", &["Warrior", "Strength"]),
+                    link("c", "", &["Warrior", "Arms"]),
+                ],
+            ),
+        ];
+        assert_eq!(
+            counts(&sections),
+            "buffs: 1 in 1 groups, traits: 3 in 2 groups"
+        );
+    }
+
+    #[test]
+    fn traits_to_lines_follows_tiers() {
+        let t = |name: &str, tier: u64, order: u64| {
+            sj::json!({
+                "name": name,
+                "icon": "t.png",
+                "specialization": 4,
+                "tier": tier,
+                "order": order
+            })
+        };
+        let traits = sj::json!([
+            t("Berserker's Power", 2, 0),
+            t("Axe Mastery", 3, 1),
+            t("Peak Performance", 1, 2),
+            t("Forceful Greatsword", 1, 0),
+        ]);
+        let names = |sort| {
+            let lines = traits_to_lines(
+                &parse_traits(traits.clone()),
+                HashMap::from([(4, strength())]),
+                &[],
+                false,
+                false,
+                UnknownSpecs::Skip,
+                sort,
+            )
+            .unwrap();
+            lines
+                .into_iter()
+                .filter_map(|line| match line {
+                    Line::Link { name, .. } => Some(name),
+                    Line::Header { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(None),
+            [
+                "Forceful Greatsword",
+                "Peak Performance",
+                "Berserker's Power",
+                "Axe Mastery"
+            ]
+        );
+        assert_eq!(
+            names(Some(SortBy::Name)),
+            [
+                "Axe Mastery",
+                "Berserker's Power",
+                "Forceful Greatsword",
+                "Peak Performance"
+            ]
+        );
+    }
+
+    #[test]
+    fn traits_to_lines_unknown_specs() {
+        let traits = sj::json!([
+            { "name": "Known", "icon": "a.png", "specialization": 4 },
+            { "name": "Orphan", "icon": "b.png", "specialization": 99 }
+        ]);
+        let specs = || HashMap::from([(4, strength())]);
+        let render = |unknown| {
+            let lines = traits_to_lines(
+                &parse_traits(traits.clone()),
+                specs(),
+                &[],
+                false,
+                false,
+                unknown,
+                None,
+            )
+            .unwrap();
+            to_markdown(&lines.iter().collect::<Vec<_>>(), LinkStyle::Reference)
+        };
+        assert_eq!(
+            render(UnknownSpecs::Skip),
+            ["# Traits", "## Warrior", "### Strength", "[Known]: a.png"]
+        );
+        assert_eq!(
+            render(UnknownSpecs::Bucket),
+            [
+                "# Traits",
+                "## Unknown",
+                "### Specialization 99",
+                "[Orphan]: b.png",
+                "## Warrior",
+                "### Strength",
+                "[Known]: a.png",
+            ]
+        );
+        assert!(matches!(
+            traits_to_lines(
+                &parse_traits(traits),
+                specs(),
+                &[],
+                false,
+                false,
+                UnknownSpecs::Fail,
+                None
+            ),
+            Err(Error::MissingSpecialization { id: 99 })
+        ));
+    }
+
+    #[test]
+    fn traits_to_lines_by_tier() {
+        let traits = sj::json!([
+            { "name": "Last Stand", "icon": "c.png", "specialization": 4,
+              "tier": 3, "slot": "Major", "order": 0 },
+            { "name": "Might Makes Right", "icon": "b.png", "specialization": 4,
+              "tier": 1, "slot": "Major", "order": 1 },
+            { "name": "Berserker's Power", "icon": "a.png", "specialization": 4,
+              "tier": 1, "slot": "Minor", "order": 0 }
+        ]);
+        let lines = traits_to_lines(
+            &parse_traits(traits),
+            HashMap::from([(4, strength())]),
+            &[],
+            false,
+            true,
+            UnknownSpecs::Skip,
+            None,
+        )
+        .unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        validate_grouping(&lines).unwrap();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            [
+                "# Traits",
+                "## Warrior",
+                "### Strength",
+                "#### Minor",
+                "[Berserker's Power]: a.png",
+                "#### Adept",
+                "[Might Makes Right]: b.png",
+                "#### Grandmaster",
+                "[Last Stand]: c.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_profession_pages() {
+        let sections = vec![
+            (
+                Category::Buffs,
+                vec![Line::header(1, "Buffs"), link("Might", "Might.png", &[])],
+            ),
+            (
+                Category::Skills,
+                vec![
+                    Line::header(1, "Skill"),
+                    Line::header(2, "Guardian"),
+                    Line::header(3, "Heal"),
+                    link("Shelter", "Shelter.png", &["Guardian", "Heal"]),
+                    Line::header(2, "Thief"),
+                    Line::header(3, "Elite"),
+                    link("Basilisk Venom", "Basilisk Venom.png", &["Thief", "Elite"]),
+                ],
+            ),
+        ];
+        let pages = split_by_profession(sections);
+        let render = |page: &str| {
+            let lines: Vec<&Line> = pages[page].iter().flat_map(|(_, lines)| lines).collect();
+            to_markdown(&lines, LinkStyle::Reference)
+        };
+        assert_eq!(
+            pages.keys().collect::<Vec<_>>(),
+            ["buffs", "guardian", "thief"]
+        );
+        assert_eq!(render("buffs"), ["# Buffs", "[Might]: Might.png"]);
+        assert_eq!(
+            render("thief"),
+            [
+                "# Skill",
+                "## Thief",
+                "### Elite",
+                "[Basilisk Venom]: Basilisk Venom.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn skills_to_lines_skips_missing_icon() {
+        let skills = sj::json!([
+            { "id": 1, "name": "Iconless", "type": "Weapon", "professions": ["Thief"] },
+            {
+                "id": 2,
+                "name": "Steal",
+                "icon": "a.png",
+                "type": "Profession",
+                "professions": ["Thief"]
+            }
+        ]);
+        let lines = skills_to_lines(&parse_skills(skills), &[], GroupBy::Type, None).unwrap();
+        let lines: Vec<&Line> = lines.iter().collect();
+        assert_eq!(
+            to_markdown(&lines, LinkStyle::Reference),
+            ["# Skill", "## Thief", "### Profession", "[Steal]: a.png"]
+        );
+    }
+
+    #[test]
+    fn table_of_contents_anchors() {
+        let headers = [
+            Line::header(1, "Skill"),
+            Line::header(2, "Guardian"),
+            Line::header(3, "Weapon"),
+            Line::header(2, "Necromancer"),
+            Line::header(3, "Weapon"),
+            Line::header(3, "Profession Mechanic: Shroud"),
+        ];
+        let lines: Vec<&Line> = headers.iter().collect();
+        assert_eq!(
+            table_of_contents(&lines),
+            [
+                "- [Skill](#skill)",
+                "  - [Guardian](#guardian)",
+                "    - [Weapon](#weapon)",
+                "  - [Necromancer](#necromancer)",
+                "    - [Weapon](#weapon-1)",
+                "    - [Profession Mechanic: Shroud](#profession-mechanic-shroud)",
+            ]
+        );
+    }
+}
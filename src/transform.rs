@@ -0,0 +1,462 @@
+//! Pure transformations of raw API json into the shapes the output is built from.
+
+use std::collections::{BTreeMap, HashMap};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json as sj;
+
+use crate::error::{Error, OrShape, Result};
+use crate::model::{Fact, Facts, Skill, Specialization, Trait};
+use crate::output::Category;
+
+/// the ids of a list endpoint such as `/v2/skills`
+pub fn to_ids(json: sj::Value) -> Result<Vec<u64>> {
+    json.as_array()
+        .or_shape("not an array")?
+        .iter()
+        .map(|x| x.as_u64())
+        .collect::<Option<Vec<u64>>>()
+        .or_shape("fail to convert to ids")
+}
+
+/// ids listed one or more per line, separated by commas, e.g. from another tool
+///
+/// A line may start with the category they belong to, as in `skills:1,2,3`; every line
+/// naming one must agree. Errors name the line they were found on.
+pub fn parse_id_list(input: &str) -> Result<(Option<Category>, Vec<u64>)> {
+    let mut category = None;
+    let mut ids = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let n = n + 1;
+        let list = match line.split_once(':') {
+            Some((name, list)) => {
+                let named = Category::from_str(name.trim(), true)
+                    .map_err(|_| Error::Validation(format!("line {n}: unknown category {name}")))?;
+                if category.is_some_and(|c| c != named) {
+                    return Err(Error::Validation(format!(
+                        "line {n}: ids of more than one category"
+                    )));
+                }
+                category = Some(named);
+                list
+            }
+            None => line,
+        };
+        for id in list.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+            let id = id
+                .parse()
+                .map_err(|_| Error::Validation(format!("line {n}: bad id {id}")))?;
+            ids.push(id);
+        }
+    }
+    Ok((category, ids))
+}
+
+/// host every icon is served from, whatever cdn the api pointed at
+const RENDER_HOST: &str = "render.guildwars2.com";
+
+/// the one spelling of an icon url: https, the canonical render host and no query
+pub fn normalize_icon_url(url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .or_else(|| url.strip_prefix("//"));
+    let Some(rest) = rest else {
+        return url.to_owned();
+    };
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.to_ascii_lowercase();
+    let host = if host.starts_with("render") && host.ends_with(".guildwars2.com") {
+        RENDER_HOST
+    } else {
+        &host
+    };
+    format!("https://{host}/{path}")
+}
+
+/// the `Buff` facts of every trait or skill in `items`
+///
+/// `traited_facts` are scanned after `facts`, as some conditions and boons only
+/// show up once a trait modifies a skill.
+fn buff_facts(items: &sj::Value, kind: &str) -> Result<Vec<Fact>> {
+    let items = Vec::<Facts>::deserialize(items).map_err(Error::json(format!("{kind} facts")))?;
+    Ok(items
+        .into_iter()
+        .flat_map(|item| item.facts.into_iter().chain(item.traited_facts))
+        .filter(|fact| fact.kind.as_deref() == Some("Buff"))
+        .collect())
+}
+
+/// every buff status mapped to its icon
+///
+/// A status carried by several facts with different icons gets the smallest icon url, so the
+/// choice does not depend on the order the api lists traits and skills in.
+pub fn get_buffs(traits: &sj::Value, skills: &sj::Value) -> Result<BTreeMap<String, String>> {
+    let mut result: BTreeMap<String, String> = BTreeMap::new();
+
+    for buff in buff_facts(traits, "traits")?
+        .into_iter()
+        .chain(buff_facts(skills, "skills")?)
+    {
+        let s = buff.status.as_deref().or_shape("buff status")?;
+        // another fact of the same buff may still carry one
+        match buff.icon.as_deref() {
+            Some(icon) => match result.get_mut(s) {
+                Some(known) if icon < known.as_str() => *known = icon.to_owned(),
+                Some(_) => {}
+                None => {
+                    result.insert(s.to_owned(), icon.to_owned());
+                }
+            },
+            None => log::warn!("skipped a fact of buff {s}: no icon"),
+        }
+    }
+
+    log::info!("buffs: found {}", result.len());
+    Ok(result)
+}
+
+/// a copy of `obj` holding only `keys`
+pub fn keep_fields(obj: &sj::Map<String, sj::Value>, keys: &[&str]) -> sj::Map<String, sj::Value> {
+    keys.iter()
+        .filter_map(|&k| Some((k.to_owned(), obj.get(k)?.clone())))
+        .collect()
+}
+
+/// `keep_fields` of every object in the array `json`
+fn keep_fields_all(json: &sj::Value, keys: &[&str]) -> Result<Vec<sj::Map<String, sj::Value>>> {
+    json.as_array()
+        .or_shape("is not an array")?
+        .iter()
+        .map(|v| Ok(keep_fields(v.as_object().or_shape("not an object")?, keys)))
+        .collect()
+}
+
+/// what sections that are just a list of links keep, e.g. pets, amulets and currencies
+const LINK_FIELDS: &[&str] = &["name", "icon"];
+const ITEM_FIELDS: &[&str] = &["name", "icon", "rarity"];
+
+/// the skills links are built from, dropping those without a type or profession
+///
+/// Skills used by more than one profession are dropped too unless `multi_profession` is set.
+/// The links between chained and flip-over skills are only kept with `chains`, and their
+/// tooltip text only with `descriptions`.
+pub fn shrink_skills(
+    json: sj::Value,
+    multi_profession: bool,
+    chains: bool,
+    descriptions: bool,
+) -> Result<Vec<Skill>> {
+    let skills = Vec::<Skill>::deserialize(json).map_err(Error::json("skills"))?;
+    let result: Vec<Skill> = skills
+        .into_iter()
+        .filter(|skill| skill.kind.is_some())
+        .filter(|skill| {
+            let count = skill.professions.len();
+            count == 1 || (multi_profession && count > 1)
+        })
+        .map(|mut skill| {
+            if !chains {
+                skill.prev_chain = None;
+                skill.next_chain = None;
+                skill.flip_skill = None;
+            }
+            if !descriptions {
+                skill.description = None;
+            }
+            skill
+        })
+        .collect();
+    log::info!("skills: kept {}", result.len());
+    Ok(result)
+}
+
+/// the traits links are built from, with their tooltip text only with `descriptions`
+pub fn shrink_traits(json: sj::Value, descriptions: bool) -> Result<Vec<Trait>> {
+    let mut result = Vec::<Trait>::deserialize(json).map_err(Error::json("traits"))?;
+    if !descriptions {
+        for t in &mut result {
+            t.description = None;
+        }
+    }
+    log::info!("traits: kept {}", result.len());
+    Ok(result)
+}
+
+/// keep the name and icon of each entry of `kind`, for a section that just lists them
+pub fn shrink_links(json: sj::Value, kind: &str) -> Result<sj::Value> {
+    let result = keep_fields_all(&json, LINK_FIELDS)?;
+    log::info!("{kind}: kept {}", result.len());
+    Ok(sj::Value::from(result))
+}
+
+/// keep the fields item links are built from, dropping items without an icon
+///
+/// When `types` is not empty, only items of those types are kept, ignoring case.
+pub fn shrink_items(json: sj::Value, types: &[String]) -> Result<sj::Value> {
+    let wanted = |v: &sj::Value| {
+        let typ = v.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+        v.get("icon").is_some()
+            && (types.is_empty() || types.iter().any(|t| t.eq_ignore_ascii_case(typ)))
+    };
+    let result = json
+        .as_array()
+        .or_shape("is not an array")?
+        .iter()
+        .filter(|v| wanted(v))
+        .map(|v| {
+            Ok(keep_fields(
+                v.as_object().or_shape("not an object")?,
+                ITEM_FIELDS,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    log::info!("items: kept {}", result.len());
+    Ok(sj::Value::from(result))
+}
+
+/// map specialization ids to their profession, name, eliteness and icon
+pub fn shrink_specializations(json: sj::Value) -> Result<HashMap<i32, Specialization>> {
+    let specs = Vec::<Specialization>::deserialize(json).map_err(Error::json("specializations"))?;
+    let result: HashMap<i32, Specialization> =
+        specs.into_iter().map(|spec| (spec.id, spec)).collect();
+    log::info!("specializations: kept {}", result.len());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_id_list_lines_and_prefix() {
+        let (category, ids) = parse_id_list("skills:1, 2\n3\n\nskills:4").unwrap();
+        assert_eq!(category, Some(Category::Skills));
+        assert_eq!(ids, [1, 2, 3, 4]);
+
+        let error = parse_id_list("1,2\n3,x").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: bad id x");
+        let error = parse_id_list("skills:1\ntraits:2").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: ids of more than one category");
+    }
+
+    #[test]
+    fn shrink_skills_keeps_single_profession() {
+        let skills = sj::json!([
+            {
+                "id": 5491,
+                "name": "Fireball",
+                "icon": "https://render.guildwars2.com/file/A/1.png",
+                "type": "Weapon",
+                "professions": ["Elementalist"],
+                "description": "Cast a fireball."
+            },
+            {
+                "id": 9093,
+                "name": "Bane Signet",
+                "icon": "https://render.guildwars2.com/file/B/2.png",
+                "type": "Utility",
+                "professions": ["Guardian", "Revenant"]
+            },
+            {
+                "id": 1,
+                "name": "No Type",
+                "icon": "https://render.guildwars2.com/file/C/3.png",
+                "professions": ["Thief"]
+            },
+            {
+                "id": 2,
+                "name": "No Profession",
+                "icon": "https://render.guildwars2.com/file/D/4.png",
+                "type": "Elite"
+            }
+        ]);
+        let kept = shrink_skills(skills, false, false, false).unwrap();
+        let names: Vec<&str> = kept.iter().map(|skill| skill.name.as_str()).collect();
+        assert_eq!(names, ["Fireball"]);
+        assert_eq!(kept[0].description, None);
+    }
+
+    #[test]
+    fn keep_fields_skips_missing_keys() {
+        let obj = sj::json!({ "name": "Fireball", "icon": "a.png", "id": 5491 });
+        assert_eq!(
+            sj::Value::from(keep_fields(obj.as_object().unwrap(), &["name", "type"])),
+            sj::json!({ "name": "Fireball" })
+        );
+    }
+
+    #[test]
+    fn normalize_icon_url_forms() {
+        let canonical = "https://render.guildwars2.com/file/A/1.png";
+        for url in [
+            canonical,
+            "http://render.guildwars2.com/file/A/1.png",
+            "https://Render-EU.guildwars2.com/file/A/1.png?v=2",
+            "//render.guildwars2.com/file/A/1.png#x",
+        ] {
+            assert_eq!(normalize_icon_url(url), canonical);
+        }
+        assert_eq!(normalize_icon_url("icons/1.png"), "icons/1.png");
+    }
+
+    #[test]
+    fn shrink_skills_multi_profession() {
+        let skills = sj::json!([{
+            "id": 9093,
+            "name": "Bane Signet",
+            "icon": "https://render.guildwars2.com/file/B/2.png",
+            "type": "Utility",
+            "professions": ["Guardian", "Revenant"],
+            "flip_skill": 9094
+        }]);
+        assert!(shrink_skills(skills.clone(), false, false, false)
+            .unwrap()
+            .is_empty());
+        let kept = shrink_skills(skills.clone(), true, false, false).unwrap();
+        assert_eq!(kept[0].professions, ["Guardian", "Revenant"]);
+        assert_eq!(kept[0].flip_skill, None);
+        let kept = shrink_skills(skills, true, true, false).unwrap();
+        assert_eq!(kept[0].flip_skill, Some(9094));
+    }
+
+    #[test]
+    fn skills_of_another_shape_are_an_error() {
+        let skills = sj::json!([{ "id": 1, "icon": "a.png", "type": "Weapon" }]);
+        assert!(matches!(
+            shrink_skills(skills, false, false, false),
+            Err(Error::Json { .. })
+        ));
+    }
+
+    #[test]
+    fn get_buffs_picks_smallest_icon() {
+        let traits = sj::json!([{
+            "facts": [
+                { "type": "Buff", "status": "Might", "icon": "trait-might.png" },
+                { "type": "Damage", "text": "Damage" }
+            ]
+        }]);
+        let skills = sj::json!([{
+            "facts": [
+                { "type": "Buff", "status": "Might", "icon": "skill-might.png" },
+                { "type": "Buff", "status": "Burning" }
+            ],
+            "traited_facts": [{ "type": "Buff", "status": "Burning", "icon": "burning.png" }]
+        }]);
+        let buffs = get_buffs(&traits, &skills).unwrap();
+        assert_eq!(buffs.len(), 2);
+        assert_eq!(buffs["Might"], "skill-might.png");
+        assert_eq!(buffs["Burning"], "burning.png");
+        // the order the facts arrive in does not matter
+        assert_eq!(get_buffs(&skills, &traits).unwrap(), buffs);
+    }
+
+    #[test]
+    fn shrink_traits_keeps_link_fields() {
+        let traits = sj::json!([{
+            "id": 214,
+            "name": "Vigorous Precision",
+            "icon": "https://render.guildwars2.com/file/E/5.png",
+            "specialization": 3,
+            "tier": 1,
+            "facts": []
+        }]);
+        let kept = shrink_traits(traits, false).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].name, "Vigorous Precision");
+        assert_eq!(kept[0].specialization, 3);
+        assert_eq!(kept[0].tier, Some(1));
+    }
+
+    #[test]
+    fn shrink_links_keeps_name_and_icon() {
+        let pets = sj::json!([{
+            "id": 1,
+            "name": "Juvenile Jungle Stalker",
+            "icon": "https://render.guildwars2.com/file/F/7.png",
+            "skills": [{ "id": 12637 }]
+        }]);
+        assert_eq!(
+            shrink_links(pets, "pets").unwrap(),
+            sj::json!([{
+                "name": "Juvenile Jungle Stalker",
+                "icon": "https://render.guildwars2.com/file/F/7.png"
+            }])
+        );
+    }
+
+    #[test]
+    fn shrink_items_filters_type() {
+        let items = sj::json!([
+            {
+                "id": 30684,
+                "name": "Frostfang",
+                "icon": "https://render.guildwars2.com/file/G/8.png",
+                "type": "Weapon",
+                "rarity": "Legendary",
+                "level": 80
+            },
+            {
+                "id": 19721,
+                "name": "Glob of Ectoplasm",
+                "icon": "https://render.guildwars2.com/file/H/9.png",
+                "type": "CraftingMaterial",
+                "rarity": "Exotic"
+            },
+            { "id": 1, "name": "No Icon", "type": "Weapon", "rarity": "Basic" }
+        ]);
+        assert_eq!(
+            shrink_items(items, &["weapon".to_owned()]).unwrap(),
+            sj::json!([{
+                "name": "Frostfang",
+                "icon": "https://render.guildwars2.com/file/G/8.png",
+                "rarity": "Legendary"
+            }])
+        );
+    }
+
+    #[test]
+    fn shrink_specializations_maps_id() {
+        let specializations = sj::json!([
+            { "id": 3, "name": "Strength", "profession": "Warrior", "elite": false },
+            {
+                "id": 18,
+                "name": "Berserker",
+                "profession": "Warrior",
+                "elite": true,
+                "icon": "https://render.guildwars2.com/file/I/10.png"
+            }
+        ]);
+        let map = shrink_specializations(specializations).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map[&3],
+            Specialization {
+                id: 3,
+                profession: "Warrior".to_owned(),
+                name: "Strength".to_owned(),
+                elite: false,
+                icon: None,
+            }
+        );
+        assert_eq!(
+            map[&18],
+            Specialization {
+                id: 18,
+                profession: "Warrior".to_owned(),
+                name: "Berserker".to_owned(),
+                elite: true,
+                icon: Some("https://render.guildwars2.com/file/I/10.png".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn shrink_specializations_rejects_missing_profession() {
+        let specializations = sj::json!([{ "id": 3, "name": "Strength" }]);
+        assert!(shrink_specializations(specializations).is_err());
+    }
+}
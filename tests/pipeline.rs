@@ -0,0 +1,607 @@
+//! The fetch, shrink and render pipeline against a mock of the GW2 API.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde_json as sj;
+use wiremock::matchers::{header, header_regex, method, path, query_param, query_param_is_missing};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use gw2img::api::{
+    broken_icons, client, download_icons, get_all, get_data, get_meta, Api, Cache, CacheMode,
+    ChunkStrategy, Lang, RateLimiter, USER_AGENT,
+};
+use gw2img::error::Error;
+use gw2img::output::{
+    buffs_to_lines, skills_to_lines, to_markdown, traits_to_lines, Category, GroupBy, Line,
+    LinkStyle, UnknownSpecs,
+};
+use gw2img::transform::{get_buffs, shrink_skills, shrink_specializations, shrink_traits, to_ids};
+
+fn api(server: &MockServer) -> Api {
+    Api {
+        client: client(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+        .unwrap(),
+        base_url: format!("{}/v2", server.uri()),
+        limiter: RateLimiter::per_minute(60_000),
+        cache: Cache::new(PathBuf::from("unused"), &server.uri(), CacheMode::Off),
+        lang: Lang::En,
+        api_key: None,
+        best_effort: false,
+        max_attempts: 5,
+        failed_chunks: AtomicUsize::new(0),
+        fetched: AtomicUsize::new(0),
+        cache_hits: AtomicUsize::new(0),
+    }
+}
+
+/// answer both the id list and the details of `category`
+async fn mount_category(server: &MockServer, category: &str, ids: &str, details: sj::Value) {
+    let ids_list: Vec<u64> = ids.split(',').map(|id| id.parse().unwrap()).collect();
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/{category}")))
+        .and(query_param_is_missing("ids"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(ids_list))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/{category}")))
+        .and(query_param("ids", ids))
+        .respond_with(ResponseTemplate::new(200).set_body_json(details))
+        .mount(server)
+        .await;
+}
+
+async fn fetch(api: &Api, category: &str) -> Result<sj::Value, Error> {
+    let ids = to_ids(get_meta(api, category).await?)?;
+    get_data(api, &ids, category, 200, ChunkStrategy::Sequential, 4).await
+}
+
+fn skills() -> sj::Value {
+    sj::json!([{
+        "id": 5491,
+        "name": "Fireball",
+        "icon": "https://render.guildwars2.com/file/A/1.png",
+        "type": "Weapon",
+        "professions": ["Elementalist"]
+    }])
+}
+
+#[tokio::test]
+async fn renders_markdown() {
+    let server = MockServer::start().await;
+    mount_category(&server, "skills", "5491", skills()).await;
+    mount_category(
+        &server,
+        "traits",
+        "214",
+        sj::json!([{
+            "id": 214,
+            "name": "Vigorous Precision",
+            "icon": "https://render.guildwars2.com/file/E/5.png",
+            "specialization": 3,
+            "facts": [{
+                "type": "Buff",
+                "status": "Vigor",
+                "icon": "https://render.guildwars2.com/file/V/6.png"
+            }]
+        }]),
+    )
+    .await;
+    mount_category(
+        &server,
+        "specializations",
+        "3",
+        sj::json!([{ "id": 3, "name": "Strength", "profession": "Warrior", "elite": false }]),
+    )
+    .await;
+    let api = api(&server);
+
+    let skills = fetch(&api, "skills").await.unwrap();
+    let traits = fetch(&api, "traits").await.unwrap();
+    let specializations = fetch(&api, "specializations").await.unwrap();
+    let mut lines = buffs_to_lines(get_buffs(&traits, &skills).unwrap(), Lang::En).unwrap();
+    lines.extend(
+        traits_to_lines(
+            &shrink_traits(traits, false).unwrap(),
+            shrink_specializations(specializations).unwrap(),
+            &[],
+            false,
+            false,
+            UnknownSpecs::Skip,
+            None,
+        )
+        .unwrap(),
+    );
+    lines.extend(
+        skills_to_lines(
+            &shrink_skills(skills, false, false, false).unwrap(),
+            &[],
+            GroupBy::Type,
+            None,
+        )
+        .unwrap(),
+    );
+    let lines: Vec<&Line> = lines.iter().collect();
+
+    assert_eq!(
+        to_markdown(&lines, LinkStyle::Reference),
+        [
+            "# Buffs",
+            "## Boons",
+            "[Vigor]: https://render.guildwars2.com/file/V/6.png",
+            "# Traits",
+            "## Warrior",
+            "### Strength",
+            "[Vigorous Precision]: https://render.guildwars2.com/file/E/5.png",
+            "# Skill",
+            "## Elementalist",
+            "### Weapon",
+            "[Fireball]: https://render.guildwars2.com/file/A/1.png",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retries_after_429() {
+    let server = MockServer::start().await;
+    // mounted first, so it answers the first detail request only
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param("ids", "5491"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_category(&server, "skills", "5491", skills()).await;
+    let api = api(&server);
+
+    assert_eq!(fetch(&api, "skills").await.unwrap(), skills());
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+}
+
+#[tokio::test]
+async fn gives_up_on_server_errors_after_max_attempts() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param("ids", "5491"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "0"))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    mount_category(&server, "skills", "5491", skills()).await;
+    let mut api = api(&server);
+    api.max_attempts = 2;
+
+    match fetch(&api, "skills").await.unwrap_err() {
+        Error::Api(text) => assert!(text.contains("503"), "{text}"),
+        error => panic!("not an api error: {error}"),
+    }
+    // the id list, then the details twice; a third try would have been answered
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn reports_api_error_text() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param_is_missing("ids"))
+        .respond_with(ResponseTemplate::new(200).set_body_json([999]))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param("ids", "999"))
+        .respond_with(
+            ResponseTemplate::new(404).set_body_json(sj::json!({ "text": "no such id: 999" })),
+        )
+        .mount(&server)
+        .await;
+    let api = api(&server);
+
+    match fetch(&api, "skills").await.unwrap_err() {
+        Error::Api(text) => assert!(text.contains("no such id: 999"), "{text}"),
+        error => panic!("not an api error: {error}"),
+    }
+}
+
+#[tokio::test]
+async fn falls_back_only_when_ids_all_is_refused() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/traits"))
+        .and(query_param("ids", "all"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_json(sj::json!({ "text": "all ids not supported for this endpoint" })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/account"))
+        .and(query_param("ids", "all"))
+        .respond_with(
+            ResponseTemplate::new(403).set_body_json(sj::json!({ "text": "Invalid access token" })),
+        )
+        .mount(&server)
+        .await;
+    let api = api(&server);
+
+    assert_eq!(get_all(&api, "traits").await.unwrap(), None);
+    match get_all(&api, "account").await.unwrap_err() {
+        Error::Api(text) => assert!(text.contains("Invalid access token"), "{text}"),
+        error => panic!("not an api error: {error}"),
+    }
+}
+
+#[tokio::test]
+async fn decompresses_gzip() {
+    use std::io::Write;
+
+    let server = MockServer::start().await;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&sj::to_vec(&skills()).unwrap()).unwrap();
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param_is_missing("ids"))
+        .respond_with(ResponseTemplate::new(200).set_body_json([5491]))
+        .mount(&server)
+        .await;
+    // only answered when the client asks for gzip
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param("ids", "5491"))
+        .and(header_regex("accept-encoding", "gzip"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-encoding", "gzip")
+                .insert_header("content-type", "application/json")
+                .set_body_bytes(encoder.finish().unwrap()),
+        )
+        .mount(&server)
+        .await;
+    let api = api(&server);
+
+    assert_eq!(fetch(&api, "skills").await.unwrap(), skills());
+}
+
+#[tokio::test]
+async fn fetches_nested_endpoint() {
+    let server = MockServer::start().await;
+    let amulets = sj::json!([{
+        "id": 1,
+        "name": "Berserker Amulet",
+        "icon": "https://render.guildwars2.com/file/J/11.png",
+        "attributes": { "Power": 1200 }
+    }]);
+    mount_category(&server, "pvp/amulets", "1", amulets.clone()).await;
+    let api = api(&server);
+
+    assert_eq!(fetch(&api, "pvp/amulets").await.unwrap(), amulets);
+}
+
+#[tokio::test]
+async fn names_itself_to_the_api() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(header("user-agent", USER_AGENT))
+        .respond_with(ResponseTemplate::new(200).set_body_json([1]))
+        .mount(&server)
+        .await;
+    let api = api(&server);
+
+    assert_eq!(get_meta(&api, "skills").await.unwrap(), sj::json!([1]));
+}
+
+#[tokio::test]
+async fn fetches_listed_ids_without_the_list() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v2/skills"))
+        .and(query_param("ids", "5491"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(skills()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let api = api(&server);
+
+    let listed = get_data(&api, &[5491], "skills", 200, ChunkStrategy::Sequential, 4).await;
+    assert_eq!(listed.unwrap(), skills());
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn merges_chunks_arriving_out_of_order() {
+    let server = MockServer::start().await;
+    // the first chunk answers last
+    for (id, delay) in [(1, 300), (2, 0), (3, 100)] {
+        Mock::given(method("GET"))
+            .and(path("/v2/skills"))
+            .and(query_param("ids", id.to_string()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([sj::json!({ "id": id })])
+                    .set_delay(Duration::from_millis(delay)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    let api = api(&server);
+
+    let data = get_data(&api, &[1, 2, 3], "skills", 1, ChunkStrategy::Sequential, 3);
+    let mut ids: Vec<u64> = data
+        .await
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|skill| skill["id"].as_u64().unwrap())
+        .collect();
+    ids.sort();
+    assert_eq!(ids, [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn links_downloaded_icons_from_the_output() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/file/A/1.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"png".to_vec()))
+        .mount(&server)
+        .await;
+    let api = api(&server);
+    let root = std::env::temp_dir().join(format!("gw2img-icons-{}", std::process::id()));
+    let mut sections = vec![(
+        Category::Skills,
+        vec![Line::Link {
+            name: "Fireball".to_owned(),
+            icon: format!("{}/file/A/1.png", server.uri()),
+            groups: Vec::new(),
+            title: None,
+        }],
+    )];
+
+    let icons = root.join("icons");
+    download_icons(&api, &mut sections, &icons, &root.join("docs"), 4)
+        .await
+        .unwrap();
+    let result = std::fs::read(icons.join("1.png"));
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(result.unwrap(), b"png");
+    match &sections[0].1[0] {
+        Line::Link { icon, .. } => assert_eq!(icon, "../icons/1.png"),
+        Line::Header { .. } => unreachable!(),
+    }
+}
+
+#[tokio::test]
+async fn replaces_partly_downloaded_icons() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/file/A/1.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"png".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let api = api(&server);
+    let root = std::env::temp_dir().join(format!("gw2img-part-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    // left behind by a run cut short
+    std::fs::write(root.join("1.png.part"), b"p").unwrap();
+    let mut sections = vec![(
+        Category::Skills,
+        vec![Line::Link {
+            name: "Fireball".to_owned(),
+            icon: format!("{}/file/A/1.png", server.uri()),
+            groups: Vec::new(),
+            title: None,
+        }],
+    )];
+
+    let result = download_icons(&api, &mut sections, &root, &root, 4).await;
+    let icon = std::fs::read(root.join("1.png"));
+    let part = root.join("1.png.part").exists();
+    std::fs::remove_dir_all(&root).unwrap();
+    result.unwrap();
+    assert_eq!(icon.unwrap(), b"png");
+    assert!(!part);
+}
+
+#[tokio::test]
+async fn diffs_remote_urls_while_downloading_icons() {
+    let server = MockServer::start().await;
+    let icon = format!("{}/file/A/1.png", server.uri());
+    mount_category(
+        &server,
+        "skills",
+        "5491",
+        sj::json!([{
+            "id": 5491,
+            "name": "Fireball",
+            "icon": icon,
+            "type": "Weapon",
+            "professions": ["Elementalist"]
+        }]),
+    )
+    .await;
+    let root = std::env::temp_dir().join(format!("gw2img-diff-{}", std::process::id()));
+    // icons are linked over https, which the mock does not speak, so this one is already here
+    std::fs::create_dir_all(root.join("icons")).unwrap();
+    std::fs::write(root.join("icons/1.png"), b"png").unwrap();
+    let run = |args: &[&str]| {
+        let base = format!("{}/v2", server.uri());
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_gw2img"));
+        command
+            .current_dir(&root)
+            .env_remove("GW2_API_BASE")
+            .args(["--api-base", &base, "--no-cache", "--skills"])
+            .args(args);
+        async move { tokio::task::spawn_blocking(move || command.output().unwrap()).await }
+    };
+
+    let first = run(&["--manifest", "manifest.json", "-o", "first.md"])
+        .await
+        .unwrap();
+    let second = run(&[
+        "--download-icons",
+        "icons",
+        "--diff-against",
+        "manifest.json",
+        "-o",
+        "diff.md",
+    ])
+    .await
+    .unwrap();
+    let diff = std::fs::read_to_string(root.join("diff.md"));
+    std::fs::remove_dir_all(&root).unwrap();
+    assert!(first.status.success(), "{first:?}");
+    assert!(second.status.success(), "{second:?}");
+    // nothing changed, although the icon is now linked to its local copy
+    assert_eq!(diff.unwrap(), "# Changes\n");
+}
+
+#[tokio::test]
+async fn overrides_the_config_file_from_the_command_line() {
+    let server = MockServer::start().await;
+    mount_category(
+        &server,
+        "skills",
+        "5491",
+        sj::json!([{
+            "id": 5491,
+            "name": "Fireball",
+            "icon": "https://render.guildwars2.com/file/A/1.png",
+            "type": "Weapon",
+            "professions": ["Elementalist"]
+        }]),
+    )
+    .await;
+    let root = std::env::temp_dir().join(format!("gw2img-config-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("gw2img.toml"),
+        "toc = true\nskip = [\"skills\"]\n",
+    )
+    .unwrap();
+    let base = format!("{}/v2", server.uri());
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_gw2img"));
+    command.current_dir(&root).env_remove("GW2_API_BASE").args([
+        "--api-base",
+        &base,
+        "--no-cache",
+        "--skills",
+        "--no-toc",
+    ]);
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let markdown = String::from_utf8(output.stdout).unwrap();
+    assert!(markdown.contains("[Fireball]"), "{markdown}");
+    assert!(!markdown.contains("](#"), "{markdown}");
+}
+
+#[tokio::test]
+async fn finds_broken_icons_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .and(path("/file/A/1.png"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("HEAD"))
+        .and(path("/file/B/2.png"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let api = api(&server);
+    let link = |name: &str, icon: &str| Line::Link {
+        name: name.to_owned(),
+        icon: format!("{}{icon}", server.uri()),
+        groups: Vec::new(),
+        title: None,
+    };
+    let sections = [
+        (Category::Buffs, vec![link("Vigor", "/file/A/1.png")]),
+        (
+            Category::Pets,
+            vec![
+                link("Jaguar", "/file/A/1.png"),
+                link("Lynx", "/file/B/2.png"),
+            ],
+        ),
+    ];
+
+    let broken = broken_icons(&api, &sections, 4).await;
+    assert_eq!(
+        broken.into_iter().collect::<Vec<_>>(),
+        [format!("{}/file/B/2.png", server.uri())]
+    );
+}
+
+#[tokio::test]
+async fn caches_details_per_entity() {
+    let server = MockServer::start().await;
+    let skill = |id: u64| sj::json!({ "id": id, "name": format!("Skill {id}") });
+    for (ids, details) in [("1,2", vec![skill(1), skill(2)]), ("3", vec![skill(3)])] {
+        Mock::given(method("GET"))
+            .and(path("/v2/skills"))
+            .and(query_param("ids", ids))
+            .respond_with(ResponseTemplate::new(200).set_body_json(details))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    let dir = std::env::temp_dir().join(format!("gw2img-cache-{}", std::process::id()));
+    let mut api = api(&server);
+    api.cache = Cache::new(dir.clone(), &api.base_url, CacheMode::Use);
+    api.cache.set_build(1).await.unwrap();
+
+    let get =
+        |ids: &'static [u64]| get_data(&api, ids, "skills", 200, ChunkStrategy::Sequential, 4);
+    assert_eq!(get(&[1, 2]).await.unwrap().as_array().unwrap().len(), 2);
+    // only the new id is requested
+    let mut names: Vec<String> = get(&[1, 2, 3])
+        .await
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|skill| skill["name"].as_str().unwrap().to_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Skill 1", "Skill 2", "Skill 3"]);
+    assert_eq!(api.fetched.load(Ordering::Relaxed), 3);
+    assert_eq!(api.cache_hits.load(Ordering::Relaxed), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn keeps_the_cache_of_each_api_base_apart() {
+    let dir = std::env::temp_dir().join(format!("gw2img-bases-{}", std::process::id()));
+    let cache = |base: &str| Cache::new(dir.clone(), base, CacheMode::Use);
+    let mut real = cache("https://api.guildwars2.com/v2");
+    real.set_build(1).await.unwrap();
+    real.store("skills_en", &sj::json!([1])).await.unwrap();
+
+    let mut mock = cache("http://127.0.0.1:8080/v2");
+    mock.set_build(1).await.unwrap();
+    let mut again = cache("https://api.guildwars2.com/v2/");
+    again.set_build(1).await.unwrap();
+    let (mock, again) = (mock.load("skills_en").await, again.load("skills_en").await);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(mock.unwrap(), None);
+    assert_eq!(again.unwrap(), Some(sj::json!([1])));
+}